@@ -0,0 +1,130 @@
+/// A character set that can be designated into G0 to G3 with SCS.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    Uk,
+    DecSpecialGraphics,
+    DecSupplemental,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CharsetIndex {
+    #[default]
+    G0,
+    G1,
+    G2,
+    G3,
+}
+
+impl Charset {
+    /// Translates a character from the GL range, `0x20` to `0x7e`.
+    fn map(self, character: char) -> char {
+        match self {
+            Self::Ascii => character,
+            Self::Uk => match character {
+                '#' => '£',
+                _ => character,
+            },
+            Self::DecSpecialGraphics => match character {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => character,
+            },
+            // Latin-1 shifted into GL, apart from the few places DEC differs.
+            Self::DecSupplemental => match character {
+                ' ' => ' ',
+                '(' => '¤',
+                'W' => 'Œ',
+                ']' => 'Ÿ',
+                'w' => 'œ',
+                '}' => 'ÿ',
+                _ => char::from(character as u8 + 0x80),
+            },
+        }
+    }
+}
+
+/// The designated G0 to G3 sets and which of them are invoked into GL and GR.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Charsets {
+    sets: [Charset; 4],
+    gl: CharsetIndex,
+    gr: CharsetIndex,
+    single_shift: Option<CharsetIndex>,
+}
+
+impl Default for Charsets {
+    fn default() -> Self {
+        Self {
+            sets: [Charset::Ascii; 4],
+            gl: CharsetIndex::G0,
+            gr: CharsetIndex::G2,
+            single_shift: None,
+        }
+    }
+}
+
+impl Charsets {
+    pub fn designate(&mut self, index: CharsetIndex, charset: Charset) {
+        self.sets[index as usize] = charset;
+    }
+
+    pub fn invoke_left(&mut self, index: CharsetIndex) {
+        self.gl = index;
+    }
+
+    pub fn invoke_right(&mut self, index: CharsetIndex) {
+        self.gr = index;
+    }
+
+    pub fn single_shift(&mut self, index: CharsetIndex) {
+        self.single_shift = Some(index);
+    }
+
+    /// Translates a printed character, using up any pending single shift.
+    pub fn map(&mut self, character: char) -> char {
+        let gl = self.single_shift.take().unwrap_or(self.gl);
+
+        match character {
+            ' '..='~' => self.sets[gl as usize].map(character),
+            // Latin-1 stands in for the 8-bit GR range, which is only remapped once an
+            // application invokes something other than ASCII there.
+            '\u{a0}'..='\u{fe}' => match self.sets[self.gr as usize] {
+                Charset::Ascii => character,
+                charset => charset.map(char::from(character as u8 - 0x80)),
+            },
+            _ => character,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+/// The system clipboard (Android's `ClipboardManager` on Android), falling back to a clipboard
+/// private to the application where there is none.
+#[derive(Default, Resource)]
+pub struct Clipboard {
+    #[cfg(not(target_os = "android"))]
+    system: Option<arboard::Clipboard>,
+    contents: String,
+}
+
+impl Clipboard {
+    pub fn set(&mut self, text: String) {
+        #[cfg(not(target_os = "android"))]
+        if let Some(system) = self.system() {
+            if let Err(error) = system.set_text(text.clone()) {
+                error!("unable to copy to clipboard: {error}");
+            }
+        }
+
+        #[cfg(target_os = "android")]
+        if let Err(error) = android::set_text(&text) {
+            error!("unable to copy to clipboard: {error}");
+        }
+
+        self.contents = text;
+    }
+
+    pub fn get(&mut self) -> String {
+        #[cfg(not(target_os = "android"))]
+        if let Some(system) = self.system() {
+            match system.get_text() {
+                Ok(text) => return text,
+                Err(error) => debug!("unable to paste from clipboard: {error}"),
+            }
+        }
+
+        #[cfg(target_os = "android")]
+        match android::get_text() {
+            Ok(Some(text)) => return text,
+            Ok(None) => {}
+            Err(error) => debug!("unable to paste from clipboard: {error}"),
+        }
+
+        self.contents.clone()
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn system(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.system.is_none() {
+            self.system = arboard::Clipboard::new()
+                .inspect_err(|error| error!("unable to open clipboard: {error}"))
+                .ok();
+        }
+
+        self.system.as_mut()
+    }
+}
+
+/// Android's `ClipboardManager`, reached through JNI like the soft keyboard.
+#[cfg(target_os = "android")]
+mod android {
+    use jni::objects::{JObject, JString, JValue};
+    use jni::{JNIEnv, JavaVM};
+
+    fn with_clipboard_manager<T>(
+        f: impl FnOnce(&mut JNIEnv, &JObject, &JObject) -> jni::errors::Result<T>,
+    ) -> jni::errors::Result<T> {
+        let app = bevy::window::ANDROID_APP.get().unwrap();
+        let vm = unsafe { JavaVM::from_raw(app.vm_as_ptr().cast())? };
+        let activity = unsafe { JObject::from_raw(app.activity_as_ptr().cast()) };
+        let mut env = vm.attach_current_thread()?;
+
+        let result = env
+            .new_string("clipboard")
+            .and_then(|service| {
+                env.call_method(
+                    &activity,
+                    "getSystemService",
+                    "(Ljava/lang/String;)Ljava/lang/Object;",
+                    &[JValue::from(&service)],
+                )
+            })
+            .and_then(|manager| manager.l())
+            .and_then(|manager| f(&mut env, &activity, &manager));
+
+        // A pending Java exception would break every later JNI call on this thread.
+        if result.is_err() {
+            env.exception_clear()?;
+        }
+
+        result
+    }
+
+    pub fn set_text(text: &str) -> jni::errors::Result<()> {
+        with_clipboard_manager(|env, _, manager| {
+            let label = env.new_string("milkshake")?;
+            let text = env.new_string(text)?;
+
+            let clip = env
+                .call_static_method(
+                    "android/content/ClipData",
+                    "newPlainText",
+                    "(Ljava/lang/CharSequence;Ljava/lang/CharSequence;)Landroid/content/ClipData;",
+                    &[JValue::from(&label), JValue::from(&text)],
+                )?
+                .l()?;
+
+            env.call_method(
+                manager,
+                "setPrimaryClip",
+                "(Landroid/content/ClipData;)V",
+                &[JValue::from(&clip)],
+            )?;
+
+            Ok(())
+        })
+    }
+
+    pub fn get_text() -> jni::errors::Result<Option<String>> {
+        with_clipboard_manager(|env, activity, manager| {
+            let clip = env
+                .call_method(
+                    manager,
+                    "getPrimaryClip",
+                    "()Landroid/content/ClipData;",
+                    &[],
+                )?
+                .l()?;
+
+            if clip.is_null() {
+                return Ok(None);
+            }
+
+            let item = env
+                .call_method(
+                    &clip,
+                    "getItemAt",
+                    "(I)Landroid/content/ClipData$Item;",
+                    &[JValue::Int(0)],
+                )?
+                .l()?;
+
+            let text = env
+                .call_method(
+                    &item,
+                    "coerceToText",
+                    "(Landroid/content/Context;)Ljava/lang/CharSequence;",
+                    &[JValue::from(activity)],
+                )?
+                .l()?;
+
+            let text = env
+                .call_method(&text, "toString", "()Ljava/lang/String;", &[])?
+                .l()?;
+
+            Ok(Some(env.get_string(&JString::from(text))?.into()))
+        })
+    }
+}
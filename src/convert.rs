@@ -1,18 +1,602 @@
-use bevy::input::keyboard::Key;
-use compact_str::CompactString;
-
-pub fn convert_key(key: &Key) -> Option<CompactString> {
-    let string = match key {
-        Key::Character(string) => string.as_str(),
-        Key::Backspace => "\x08",
-        Key::Enter => "\n",
-        Key::Space => " ",
-        Key::ArrowUp => "\x1bOA",
-        Key::ArrowDown => "\x1bOB",
-        Key::ArrowRight => "\x1bOC",
-        Key::ArrowLeft => "\x1bOD",
+use bevy::input::keyboard::{Key, KeyCode, KeyboardInput};
+use bevy::input::{ButtonInput, ButtonState};
+use compact_str::{format_compact, CompactString};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+const DISAMBIGUATE: u8 = 1;
+const REPORT_EVENTS: u8 = 2;
+const REPORT_ALTERNATES: u8 = 4;
+const REPORT_ALL: u8 = 8;
+const REPORT_TEXT: u8 = 16;
+
+/// The kitty functional key numbers of the keypad, from `KP_0` to `KP_BEGIN`.
+const KEYPAD: RangeInclusive<u32> = 57399..=57427;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    pub fn new(keys: &ButtonInput<KeyCode>) -> Self {
+        Self {
+            shift: keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            alt: keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+            control: keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+            super_key: keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight]),
+        }
+    }
+
+    /// The xterm modifier parameter, `1` when no modifier is held.
+    pub fn parameter(self) -> u8 {
+        1 + self.shift as u8
+            + ((self.alt as u8) << 1)
+            + ((self.control as u8) << 2)
+            + ((self.super_key as u8) << 3)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct KeyModes {
+    pub application_cursor: bool,
+    pub application_keypad: bool,
+    pub keyboard_flags: u8,
+}
+
+pub fn convert_key(
+    event: &KeyboardInput,
+    modifiers: Modifiers,
+    modes: KeyModes,
+) -> Option<CompactString> {
+    if modes.keyboard_flags != 0 {
+        if let Some(string) = kitty(event, modifiers, modes.keyboard_flags) {
+            return Some(string);
+        }
+    }
+
+    if !event.state.is_pressed() {
+        return None;
+    }
+
+    if modes.application_keypad {
+        if let Some(final_byte) = keypad(event.key_code) {
+            return Some(format_compact!("\x1bO{final_byte}"));
+        }
+    }
+
+    let string = match &event.logical_key {
+        Key::Character(string) => {
+            let mut characters = string.chars();
+
+            match (characters.next(), characters.next()) {
+                (Some(character), None) if modifiers.control => match control(character) {
+                    Some(byte) => meta(modifiers, (byte as char).encode_utf8(&mut [0; 4])),
+                    None => meta(modifiers, string),
+                },
+                _ => meta(modifiers, string),
+            }
+        }
+        Key::Space if modifiers.control => meta(modifiers, "\0"),
+        Key::Space => meta(modifiers, " "),
+        Key::Enter => meta(modifiers, "\r"),
+        Key::Tab if modifiers.shift => "\x1b[Z".into(),
+        Key::Tab => meta(modifiers, "\t"),
+        Key::Backspace if modifiers.control => meta(modifiers, "\x08"),
+        Key::Backspace => meta(modifiers, "\x7f"),
+        Key::Escape => meta(modifiers, "\x1b"),
+        Key::ArrowUp => cursor(modifiers, modes, 'A'),
+        Key::ArrowDown => cursor(modifiers, modes, 'B'),
+        Key::ArrowRight => cursor(modifiers, modes, 'C'),
+        Key::ArrowLeft => cursor(modifiers, modes, 'D'),
+        Key::Home => cursor(modifiers, modes, 'H'),
+        Key::End => cursor(modifiers, modes, 'F'),
+        Key::Insert => tilde(modifiers, 2),
+        Key::Delete => tilde(modifiers, 3),
+        Key::PageUp => tilde(modifiers, 5),
+        Key::PageDown => tilde(modifiers, 6),
+        Key::F1 => function(modifiers, 'P'),
+        Key::F2 => function(modifiers, 'Q'),
+        Key::F3 => function(modifiers, 'R'),
+        Key::F4 => function(modifiers, 'S'),
+        Key::F5 => tilde(modifiers, 15),
+        Key::F6 => tilde(modifiers, 17),
+        Key::F7 => tilde(modifiers, 18),
+        Key::F8 => tilde(modifiers, 19),
+        Key::F9 => tilde(modifiers, 20),
+        Key::F10 => tilde(modifiers, 21),
+        Key::F11 => tilde(modifiers, 23),
+        Key::F12 => tilde(modifiers, 24),
+        _ => return None,
+    };
+
+    Some(string)
+}
+
+/// Encodes a key as `CSI number;modifiers u` (or its legacy final byte) as described by the kitty
+/// keyboard protocol, returning `None` when the legacy encoding should be used instead.
+fn kitty(event: &KeyboardInput, modifiers: Modifiers, flags: u8) -> Option<CompactString> {
+    if !event.state.is_pressed() && flags & REPORT_EVENTS == 0 {
+        return None;
+    }
+
+    let event_type = match (event.state, event.repeat) {
+        _ if flags & REPORT_EVENTS == 0 => 1,
+        (ButtonState::Released, _) => 3,
+        (_, true) => 2,
+        _ => 1,
+    };
+
+    let all = flags & REPORT_ALL != 0;
+    let disambiguate = flags & DISAMBIGUATE != 0 || all;
+    let parameter = modifiers.parameter();
+    let mut alternate = None;
+    let mut text = None;
+
+    // Disambiguating already tells keypad keys apart, other functional keys are only reported
+    // along with all keys.
+    let functional = kitty_functional(event.key_code)
+        .filter(|number| all || (disambiguate && KEYPAD.contains(number)));
+
+    let (number, final_byte, encode) = match (&event.logical_key, functional) {
+        (_, Some(number)) => (number, 'u', true),
+        (Key::Character(string), _) => {
+            let character = string.chars().next()?;
+            let lowercase = character.to_lowercase().next()?;
+
+            // Shifted symbols are their own lowercase, so the unshifted key comes from where the
+            // key sits on the keyboard instead.
+            let base = match unshifted(event.key_code) {
+                Some(base) if modifiers.shift && lowercase == character => base,
+                _ => lowercase,
+            };
+
+            if flags & REPORT_ALTERNATES != 0 && modifiers.shift && base != character {
+                alternate = Some(character as u32);
+            }
+
+            text = Some(string.as_str());
+
+            let modified = modifiers.alt || modifiers.control || modifiers.super_key;
+            let encode = all || event_type != 1 || (disambiguate && modified);
+
+            (base as u32, 'u', encode)
+        }
+        (Key::Space, _) => {
+            text = Some(" ");
+
+            let encode = all || event_type != 1 || (disambiguate && parameter != 1);
+
+            (32, 'u', encode)
+        }
+        (Key::Escape, _) => (27, 'u', disambiguate),
+        (Key::Enter, _) => (
+            13,
+            'u',
+            all || (disambiguate && parameter != 1 && event_type != 3),
+        ),
+        (Key::Tab, _) => (
+            9,
+            'u',
+            all || (disambiguate && parameter != 1 && event_type != 3),
+        ),
+        (Key::Backspace, _) => (
+            127,
+            'u',
+            all || (disambiguate && parameter != 1 && event_type != 3),
+        ),
+        (Key::Insert, _) => (2, '~', event_type != 1),
+        (Key::Delete, _) => (3, '~', event_type != 1),
+        (Key::PageUp, _) => (5, '~', event_type != 1),
+        (Key::PageDown, _) => (6, '~', event_type != 1),
+        (Key::ArrowUp, _) => (1, 'A', event_type != 1),
+        (Key::ArrowDown, _) => (1, 'B', event_type != 1),
+        (Key::ArrowRight, _) => (1, 'C', event_type != 1),
+        (Key::ArrowLeft, _) => (1, 'D', event_type != 1),
+        (Key::Home, _) => (1, 'H', event_type != 1),
+        (Key::End, _) => (1, 'F', event_type != 1),
+        (Key::F1, _) => (1, 'P', event_type != 1),
+        (Key::F2, _) => (1, 'Q', event_type != 1),
+        (Key::F3, _) => (13, '~', true),
+        (Key::F4, _) => (1, 'S', event_type != 1),
+        (Key::F5, _) => (15, '~', event_type != 1),
+        (Key::F6, _) => (17, '~', event_type != 1),
+        (Key::F7, _) => (18, '~', event_type != 1),
+        (Key::F8, _) => (19, '~', event_type != 1),
+        (Key::F9, _) => (20, '~', event_type != 1),
+        (Key::F10, _) => (21, '~', event_type != 1),
+        (Key::F11, _) => (23, '~', event_type != 1),
+        (Key::F12, _) => (24, '~', event_type != 1),
+        _ => return None,
+    };
+
+    if !encode {
+        return None;
+    }
+
+    let text = text.filter(|_| flags & REPORT_TEXT != 0 && all && event_type != 3);
+
+    let mut sequence = CompactString::from("\x1b[");
+    let has_parameters = parameter != 1 || event_type != 1 || text.is_some();
+
+    if final_byte == 'u' || number != 1 || has_parameters {
+        let _ = write!(sequence, "{number}");
+    }
+
+    if let Some(alternate) = alternate {
+        let _ = write!(sequence, ":{alternate}");
+    }
+
+    if has_parameters {
+        let _ = write!(sequence, ";{parameter}");
+
+        if event_type != 1 {
+            let _ = write!(sequence, ":{event_type}");
+        }
+    }
+
+    if let Some(text) = text {
+        let codepoints = text
+            .chars()
+            .map(|character| (character as u32).to_string())
+            .collect::<Vec<_>>();
+
+        let _ = write!(sequence, ";{}", codepoints.join(":"));
+    }
+
+    sequence.push(final_byte);
+
+    Some(sequence)
+}
+
+fn kitty_functional(key_code: KeyCode) -> Option<u32> {
+    let number = match key_code {
+        KeyCode::CapsLock => 57358,
+        KeyCode::Numpad0 => 57399,
+        KeyCode::Numpad1 => 57400,
+        KeyCode::Numpad2 => 57401,
+        KeyCode::Numpad3 => 57402,
+        KeyCode::Numpad4 => 57403,
+        KeyCode::Numpad5 => 57404,
+        KeyCode::Numpad6 => 57405,
+        KeyCode::Numpad7 => 57406,
+        KeyCode::Numpad8 => 57407,
+        KeyCode::Numpad9 => 57408,
+        KeyCode::NumpadDecimal => 57409,
+        KeyCode::NumpadDivide => 57410,
+        KeyCode::NumpadMultiply => 57411,
+        KeyCode::NumpadSubtract => 57412,
+        KeyCode::NumpadAdd => 57413,
+        KeyCode::NumpadEnter => 57414,
+        KeyCode::NumpadEqual => 57415,
+        KeyCode::ShiftLeft => 57441,
+        KeyCode::ControlLeft => 57442,
+        KeyCode::AltLeft => 57443,
+        KeyCode::SuperLeft => 57444,
+        KeyCode::ShiftRight => 57447,
+        KeyCode::ControlRight => 57448,
+        KeyCode::AltRight => 57449,
+        KeyCode::SuperRight => 57450,
+        _ => return None,
+    };
+
+    Some(number)
+}
+
+/// The character a key produces without shift on a US layout, for keys whose shifted symbol
+/// can't be lowercased back into it.
+fn unshifted(key_code: KeyCode) -> Option<char> {
+    let character = match key_code {
+        KeyCode::Digit0 => '0',
+        KeyCode::Digit1 => '1',
+        KeyCode::Digit2 => '2',
+        KeyCode::Digit3 => '3',
+        KeyCode::Digit4 => '4',
+        KeyCode::Digit5 => '5',
+        KeyCode::Digit6 => '6',
+        KeyCode::Digit7 => '7',
+        KeyCode::Digit8 => '8',
+        KeyCode::Digit9 => '9',
+        KeyCode::Backquote => '`',
+        KeyCode::Minus => '-',
+        KeyCode::Equal => '=',
+        KeyCode::BracketLeft => '[',
+        KeyCode::BracketRight => ']',
+        KeyCode::Backslash => '\\',
+        KeyCode::Semicolon => ';',
+        KeyCode::Quote => '\'',
+        KeyCode::Comma => ',',
+        KeyCode::Period => '.',
+        KeyCode::Slash => '/',
+        _ => return None,
+    };
+
+    Some(character)
+}
+
+/// Encodes pasted text, wrapped in `CSI 200~` and `CSI 201~` when bracketed paste is enabled.
+pub fn paste(text: &str, bracketed: bool) -> CompactString {
+    if !bracketed {
+        return text.replace("\r\n", "\r").replace('\n', "\r").into();
+    }
+
+    let mut text = text.to_string();
+
+    for marker in ["\x1b[200~", "\x1b[201~"] {
+        while let Some(index) = text.find(marker) {
+            text.replace_range(index..index + marker.len(), "");
+        }
+    }
+
+    format_compact!("\x1b[200~{text}\x1b[201~")
+}
+
+fn control(character: char) -> Option<u8> {
+    let byte = match character {
+        'a'..='z' | 'A'..='Z' => character as u8 & 0x1f,
+        '@' | '2' | ' ' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' | '~' => 0x1e,
+        '_' | '7' | '-' | '/' => 0x1f,
+        '?' | '8' => 0x7f,
         _ => return None,
     };
 
-    Some(string.into())
+    Some(byte)
+}
+
+/// Alt sends the key prefixed with ESC.
+fn meta(modifiers: Modifiers, string: &str) -> CompactString {
+    if modifiers.alt {
+        format_compact!("\x1b{string}")
+    } else {
+        string.into()
+    }
+}
+
+fn cursor(modifiers: Modifiers, modes: KeyModes, final_byte: char) -> CompactString {
+    match modifiers.parameter() {
+        1 if modes.application_cursor => format_compact!("\x1bO{final_byte}"),
+        1 => format_compact!("\x1b[{final_byte}"),
+        parameter => format_compact!("\x1b[1;{parameter}{final_byte}"),
+    }
+}
+
+fn function(modifiers: Modifiers, final_byte: char) -> CompactString {
+    match modifiers.parameter() {
+        1 => format_compact!("\x1bO{final_byte}"),
+        parameter => format_compact!("\x1b[1;{parameter}{final_byte}"),
+    }
+}
+
+fn tilde(modifiers: Modifiers, number: u8) -> CompactString {
+    match modifiers.parameter() {
+        1 => format_compact!("\x1b[{number}~"),
+        parameter => format_compact!("\x1b[{number};{parameter}~"),
+    }
+}
+
+fn keypad(key_code: KeyCode) -> Option<char> {
+    let final_byte = match key_code {
+        KeyCode::Numpad0 => 'p',
+        KeyCode::Numpad1 => 'q',
+        KeyCode::Numpad2 => 'r',
+        KeyCode::Numpad3 => 's',
+        KeyCode::Numpad4 => 't',
+        KeyCode::Numpad5 => 'u',
+        KeyCode::Numpad6 => 'v',
+        KeyCode::Numpad7 => 'w',
+        KeyCode::Numpad8 => 'x',
+        KeyCode::Numpad9 => 'y',
+        KeyCode::NumpadMultiply => 'j',
+        KeyCode::NumpadAdd => 'k',
+        KeyCode::NumpadComma => 'l',
+        KeyCode::NumpadSubtract => 'm',
+        KeyCode::NumpadDecimal => 'n',
+        KeyCode::NumpadDivide => 'o',
+        KeyCode::NumpadEnter => 'M',
+        KeyCode::NumpadEqual => 'X',
+        _ => return None,
+    };
+
+    Some(final_byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Entity;
+
+    const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        alt: false,
+        control: false,
+        super_key: false,
+    };
+
+    const CONTROL: Modifiers = Modifiers {
+        shift: false,
+        alt: false,
+        control: true,
+        super_key: false,
+    };
+
+    fn key(key_code: KeyCode, logical_key: Key) -> KeyboardInput {
+        KeyboardInput {
+            key_code,
+            logical_key,
+            state: ButtonState::Pressed,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        }
+    }
+
+    fn character(key_code: KeyCode, character: &str) -> KeyboardInput {
+        key(key_code, Key::Character(character.into()))
+    }
+
+    fn xterm(event: &KeyboardInput, modifiers: Modifiers, modes: KeyModes) -> Option<String> {
+        convert_key(event, modifiers, modes).map(String::from)
+    }
+
+    fn kitty(event: &KeyboardInput, modifiers: Modifiers, keyboard_flags: u8) -> Option<String> {
+        let modes = KeyModes {
+            keyboard_flags,
+            ..KeyModes::default()
+        };
+
+        convert_key(event, modifiers, modes).map(String::from)
+    }
+
+    #[test]
+    fn disambiguate_keys() {
+        let a = character(KeyCode::KeyA, "a");
+        let escape = key(KeyCode::Escape, Key::Escape);
+        let up = key(KeyCode::ArrowUp, Key::ArrowUp);
+
+        assert_eq!(kitty(&a, Modifiers::default(), DISAMBIGUATE).unwrap(), "a");
+        assert_eq!(kitty(&a, CONTROL, DISAMBIGUATE).unwrap(), "\x1b[97;5u");
+        assert_eq!(
+            kitty(&escape, Modifiers::default(), DISAMBIGUATE).unwrap(),
+            "\x1b[27u"
+        );
+        assert_eq!(
+            kitty(&up, Modifiers::default(), DISAMBIGUATE).unwrap(),
+            "\x1b[A"
+        );
+    }
+
+    #[test]
+    fn report_unshifted_and_alternate_keys() {
+        let exclamation = character(KeyCode::Digit1, "!");
+        let capital = character(KeyCode::KeyA, "A");
+
+        assert_eq!(kitty(&exclamation, SHIFT, DISAMBIGUATE).unwrap(), "!");
+        assert_eq!(
+            kitty(&exclamation, SHIFT, REPORT_ALL).unwrap(),
+            "\x1b[49;2u"
+        );
+        assert_eq!(
+            kitty(&exclamation, SHIFT, REPORT_ALL | REPORT_ALTERNATES).unwrap(),
+            "\x1b[49:33;2u"
+        );
+        assert_eq!(kitty(&capital, SHIFT, REPORT_ALL).unwrap(), "\x1b[97;2u");
+        assert_eq!(
+            kitty(&capital, SHIFT, REPORT_ALL | REPORT_ALTERNATES).unwrap(),
+            "\x1b[97:65;2u"
+        );
+    }
+
+    #[test]
+    fn disambiguate_keypad_keys() {
+        let one = character(KeyCode::Numpad1, "1");
+        let shift = key(KeyCode::ShiftLeft, Key::Shift);
+
+        assert_eq!(
+            kitty(&one, Modifiers::default(), DISAMBIGUATE).unwrap(),
+            "\x1b[57400u"
+        );
+        assert_eq!(kitty(&shift, SHIFT, DISAMBIGUATE), None);
+        assert_eq!(kitty(&shift, SHIFT, REPORT_ALL).unwrap(), "\x1b[57441;2u");
+    }
+
+    #[test]
+    fn report_events_and_text() {
+        let mut a = character(KeyCode::KeyA, "a");
+
+        assert_eq!(
+            kitty(&a, Modifiers::default(), REPORT_ALL | REPORT_TEXT).unwrap(),
+            "\x1b[97;1;97u"
+        );
+
+        a.repeat = true;
+
+        assert_eq!(
+            kitty(&a, CONTROL, DISAMBIGUATE | REPORT_EVENTS).unwrap(),
+            "\x1b[97;5:2u"
+        );
+
+        a.state = ButtonState::Released;
+
+        assert_eq!(kitty(&a, CONTROL, DISAMBIGUATE), None);
+        assert_eq!(
+            kitty(&a, CONTROL, DISAMBIGUATE | REPORT_EVENTS).unwrap(),
+            "\x1b[97;5:3u"
+        );
+    }
+
+    #[test]
+    fn modify_cursor_and_function_keys() {
+        let none = Modifiers::default();
+        let modes = KeyModes::default();
+        let up = key(KeyCode::ArrowUp, Key::ArrowUp);
+        let shift_alt = Modifiers { alt: true, ..SHIFT };
+
+        assert_eq!(xterm(&up, none, modes).unwrap(), "\x1b[A");
+        assert_eq!(xterm(&up, CONTROL, modes).unwrap(), "\x1b[1;5A");
+        assert_eq!(xterm(&up, shift_alt, modes).unwrap(), "\x1b[1;4A");
+
+        let f1 = key(KeyCode::F1, Key::F1);
+        let f5 = key(KeyCode::F5, Key::F5);
+        let delete = key(KeyCode::Delete, Key::Delete);
+
+        assert_eq!(xterm(&f1, none, modes).unwrap(), "\x1bOP");
+        assert_eq!(xterm(&f1, CONTROL, modes).unwrap(), "\x1b[1;5P");
+        assert_eq!(xterm(&f5, none, modes).unwrap(), "\x1b[15~");
+        assert_eq!(xterm(&delete, SHIFT, modes).unwrap(), "\x1b[3;2~");
+    }
+
+    #[test]
+    fn application_cursor_and_keypad() {
+        let none = Modifiers::default();
+        let up = key(KeyCode::ArrowUp, Key::ArrowUp);
+        let five = character(KeyCode::Numpad5, "5");
+        let enter = key(KeyCode::NumpadEnter, Key::Enter);
+        let modes = KeyModes {
+            application_cursor: true,
+            application_keypad: true,
+            ..KeyModes::default()
+        };
+
+        assert_eq!(xterm(&up, none, modes).unwrap(), "\x1bOA");
+        assert_eq!(xterm(&up, CONTROL, modes).unwrap(), "\x1b[1;5A");
+        assert_eq!(xterm(&five, none, modes).unwrap(), "\x1bOu");
+        assert_eq!(xterm(&enter, none, modes).unwrap(), "\x1bOM");
+        assert_eq!(xterm(&five, none, KeyModes::default()).unwrap(), "5");
+        assert_eq!(xterm(&enter, none, KeyModes::default()).unwrap(), "\r");
+    }
+
+    #[test]
+    fn control_and_alt_characters() {
+        let none = Modifiers::default();
+        let modes = KeyModes::default();
+        let alt = Modifiers { alt: true, ..none };
+        let control_alt = Modifiers {
+            alt: true,
+            ..CONTROL
+        };
+        let c = character(KeyCode::KeyC, "c");
+        let space = key(KeyCode::Space, Key::Space);
+        let tab = key(KeyCode::Tab, Key::Tab);
+        let backspace = key(KeyCode::Backspace, Key::Backspace);
+
+        assert_eq!(xterm(&c, alt, modes).unwrap(), "\x1bc");
+        assert_eq!(xterm(&c, CONTROL, modes).unwrap(), "\x03");
+        assert_eq!(xterm(&c, control_alt, modes).unwrap(), "\x1b\x03");
+        assert_eq!(xterm(&space, CONTROL, modes).unwrap(), "\0");
+        assert_eq!(xterm(&tab, SHIFT, modes).unwrap(), "\x1b[Z");
+        assert_eq!(xterm(&backspace, none, modes).unwrap(), "\x7f");
+        assert_eq!(xterm(&backspace, CONTROL, modes).unwrap(), "\x08");
+
+        let mut released = c;
+
+        released.state = ButtonState::Released;
+
+        assert_eq!(xterm(&released, none, modes), None);
+    }
 }
@@ -68,6 +68,7 @@ pub fn default(asset_server: &AssetServer) -> TerminalFonts {
 
 use super::TerminalFonts;
 use bevy::prelude::*;
+use ttf_parser::Face;
 
 pub fn default(asset_server: &AssetServer) -> TerminalFonts {
     TerminalFonts {
@@ -81,3 +82,23 @@ pub fn default(asset_server: &AssetServer) -> TerminalFonts {
             .load("embedded://milkshake_terminal/../assets/fonts/RobotoMono-BoldItalic.ttf"),
     }
 }
+
+/// Measures a cell of the regular font at `font_size`, or returns `None` until it has loaded.
+pub fn cell_size(
+    fonts: &Assets<Font>,
+    terminal_fonts: &TerminalFonts,
+    font_size: f32,
+) -> Option<Vec2> {
+    let font = fonts.get(&terminal_fonts.regular)?;
+    let face = Face::parse(&font.data, 0).ok()?;
+    let scale = font_size / f32::from(face.units_per_em());
+
+    let advance = face
+        .glyph_index('0')
+        .and_then(|glyph| face.glyph_hor_advance(glyph))?;
+
+    let height =
+        i32::from(face.ascender()) - i32::from(face.descender()) + i32::from(face.line_gap());
+
+    Some((Vec2::new(f32::from(advance), height as f32) * scale).ceil())
+}
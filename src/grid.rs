@@ -0,0 +1,506 @@
+use crate::vte::{AnsiColor, UnderlineStyle};
+use std::collections::VecDeque;
+use std::mem;
+use std::ops::Range;
+
+/// A cell on the screen, or a size in cells.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Position {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Position {
+    pub const ZERO: Self = Self::new(0, 0);
+
+    pub const fn new(x: u32, y: u32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Self::new(self.x.min(other.x), self.y.min(other.y))
+    }
+}
+
+impl From<Position> for (u32, u32) {
+    fn from(Position { x, y }: Position) -> Self {
+        (x, y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TerminalStyle {
+    pub foreground: Option<AnsiColor>,
+    pub background: Option<AnsiColor>,
+    pub underline_color: Option<AnsiColor>,
+    pub underline: UnderlineStyle,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+    pub overline: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cell {
+    pub character: char,
+    /// Combining marks and the rest of a grapheme cluster that starts with `character`.
+    pub combining: Vec<char>,
+    pub style: TerminalStyle,
+    pub wide: bool,
+    pub wide_spacer: bool,
+    /// How many cells a horizontal tab skipped from here, so copying brings the tab back.
+    pub tab: u32,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            character: ' ',
+            combining: Vec::new(),
+            style: TerminalStyle::default(),
+            wide: false,
+            wide_spacer: false,
+            tab: 0,
+        }
+    }
+}
+
+impl Cell {
+    pub fn new(character: char, style: TerminalStyle) -> Self {
+        Self {
+            character,
+            style,
+            ..Self::default()
+        }
+    }
+
+    /// A blank cell as left behind by an erase, keeping the current background.
+    pub fn blank(style: TerminalStyle) -> Self {
+        Self {
+            style: TerminalStyle {
+                background: style.background,
+                ..TerminalStyle::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::from(self.character);
+
+        text.extend(&self.combining);
+        text
+    }
+
+    pub fn is_blank(&self) -> bool {
+        let TerminalStyle {
+            background,
+            underline,
+            inverse,
+            strikethrough,
+            overline,
+            ..
+        } = self.style;
+
+        self.character == ' '
+            && self.combining.is_empty()
+            && background.is_none()
+            && underline == UnderlineStyle::None
+            && !(inverse || strikethrough || overline)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    pub wrapped: bool,
+}
+
+impl Row {
+    pub fn new(columns: u32, blank: Cell) -> Self {
+        Self {
+            cells: vec![blank; columns as usize],
+            wrapped: false,
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        !self.wrapped && self.cells.iter().all(Cell::is_blank)
+    }
+
+    pub fn clear(&mut self, range: Range<u32>, blank: Cell) {
+        let end = (range.end as usize).min(self.cells.len());
+        let start = (range.start as usize).min(end);
+
+        self.cells[start..end].fill(blank);
+    }
+
+    pub fn insert_cells(&mut self, x: u32, count: u32, blank: Cell) {
+        let Some(cells) = self.cells.get_mut(x as usize..) else {
+            return;
+        };
+
+        let count = (count as usize).min(cells.len());
+
+        cells.rotate_right(count);
+        cells[..count].fill(blank);
+    }
+
+    pub fn delete_cells(&mut self, x: u32, count: u32, blank: Cell) {
+        let Some(cells) = self.cells.get_mut(x as usize..) else {
+            return;
+        };
+
+        let count = (count as usize).min(cells.len());
+        let end = cells.len();
+
+        cells.rotate_left(count);
+        cells[end - count..].fill(blank);
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Grid {
+    size: Position,
+    rows: Vec<Row>,
+    scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    display_offset: usize,
+    trimmed: usize,
+}
+
+impl Grid {
+    pub fn new(size: Position, scrollback_limit: usize) -> Self {
+        Self {
+            size,
+            rows: vec![Row::new(size.x, Cell::default()); size.y as usize],
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            display_offset: 0,
+            trimmed: 0,
+        }
+    }
+
+    pub fn size(&self) -> Position {
+        self.size
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+
+    pub fn scroll_display(&mut self, delta: isize) {
+        self.display_offset = self
+            .display_offset
+            .saturating_add_signed(delta)
+            .min(self.scrollback.len());
+    }
+
+    pub fn reset_display(&mut self) {
+        self.display_offset = 0;
+    }
+
+    pub fn viewport(&self) -> impl Iterator<Item = &Row> {
+        let start = self.scrollback.len() - self.display_offset;
+
+        self.scrollback
+            .range(start..)
+            .chain(&self.rows)
+            .take(self.size.y as usize)
+    }
+
+    /// The line number of the top of the viewport, counting from the oldest line ever scrolled into
+    /// history so that it stays put while output scrolls.
+    pub fn viewport_start(&self) -> usize {
+        self.trimmed + self.scrollback.len() - self.display_offset
+    }
+
+    pub fn line(&self, line: usize) -> Option<&Row> {
+        let index = line.checked_sub(self.trimmed)?;
+
+        match index.checked_sub(self.scrollback.len()) {
+            Some(index) => self.rows.get(index),
+            None => self.scrollback.get(index),
+        }
+    }
+
+    pub fn set_scrollback_limit(&mut self, scrollback_limit: usize) {
+        self.scrollback_limit = scrollback_limit;
+        self.trim_scrollback();
+    }
+
+    pub fn clear_scrollback(&mut self) {
+        self.trimmed += self.scrollback.len();
+        self.scrollback.clear();
+        self.display_offset = 0;
+    }
+
+    fn trim_scrollback(&mut self) {
+        let excess = self.scrollback.len().saturating_sub(self.scrollback_limit);
+
+        self.scrollback.drain(..excess);
+        self.trimmed += excess;
+        self.display_offset = self.display_offset.min(self.scrollback.len());
+    }
+
+    pub fn scroll_up(&mut self, region: Range<u32>, count: u32, blank: Cell) {
+        if region.start == 0 && self.scrollback_limit > 0 {
+            let count = count.min(region.len() as u32) as usize;
+
+            self.scrollback.extend(self.rows[..count].iter().cloned());
+
+            if self.display_offset > 0 {
+                self.display_offset += count;
+            }
+
+            self.trim_scrollback();
+        }
+
+        self.delete_rows(region, count, blank);
+    }
+
+    pub fn delete_rows(&mut self, region: Range<u32>, count: u32, blank: Cell) {
+        let region = region.start as usize..region.end as usize;
+        let count = (count as usize).min(region.len());
+        let rows = &mut self.rows[region];
+        let end = rows.len();
+
+        rows.rotate_left(count);
+
+        for row in &mut rows[end - count..] {
+            row.clear(0..self.size.x, blank.clone());
+            row.wrapped = false;
+        }
+    }
+
+    pub fn insert_rows(&mut self, region: Range<u32>, count: u32, blank: Cell) {
+        let region = region.start as usize..region.end as usize;
+        let count = (count as usize).min(region.len());
+        let rows = &mut self.rows[region];
+
+        rows.rotate_right(count);
+
+        for row in &mut rows[..count] {
+            row.clear(0..self.size.x, blank.clone());
+            row.wrapped = false;
+        }
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut Row> {
+        self.rows.get_mut(y as usize)
+    }
+
+    pub fn cell_mut(&mut self, position: Position) -> Option<&mut Cell> {
+        self.rows
+            .get_mut(position.y as usize)
+            .and_then(|row| row.cells.get_mut(position.x as usize))
+    }
+
+    pub fn clear(&mut self, blank: Cell) {
+        for row in &mut self.rows {
+            row.clear(0..self.size.x, blank.clone());
+            row.wrapped = false;
+        }
+    }
+
+    /// Resizes the grid, rewrapping wrapped lines to the new width, and returns where the cursor
+    /// ends up.
+    pub fn resize(&mut self, size: Position, cursor_position: Position) -> Position {
+        let cursor_row = self.scrollback.len() + cursor_position.y as usize;
+        let mut rows = self
+            .scrollback
+            .drain(..)
+            .chain(self.rows.drain(..))
+            .collect::<Vec<_>>();
+
+        while rows.len() > cursor_row + 1 && rows.last().is_some_and(Row::is_blank) {
+            rows.pop();
+        }
+
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let mut cursor = None;
+
+        for (y, row) in rows.into_iter().enumerate() {
+            if y == cursor_row {
+                cursor = Some(line.len() + cursor_position.x as usize);
+            }
+
+            line.extend(row.cells);
+
+            if !row.wrapped {
+                lines.push((mem::take(&mut line), cursor.take()));
+            }
+        }
+
+        if !line.is_empty() {
+            lines.push((line, cursor));
+        }
+
+        let mut rows = Vec::new();
+        let mut new_cursor = Position::ZERO;
+
+        for (mut cells, cursor) in lines {
+            let len = cells
+                .iter()
+                .rposition(|cell| !cell.is_blank())
+                .map_or(0, |index| index + 1)
+                .max(cursor.map_or(0, |cursor| cursor + 1));
+
+            cells.resize(len, Cell::default());
+
+            let mut row = Row::new(size.x, Cell::default());
+            let mut x = 0u32;
+
+            for (offset, cell) in cells.into_iter().enumerate() {
+                if cell.wide_spacer {
+                    if cursor == Some(offset) {
+                        new_cursor = Position::new(x.saturating_sub(1), rows.len() as u32);
+                    }
+
+                    continue;
+                }
+
+                let width = if cell.wide { 2 } else { 1 };
+
+                if x > 0 && x + width > size.x {
+                    let next = Row::new(size.x, Cell::default());
+
+                    row.wrapped = true;
+                    rows.push(mem::replace(&mut row, next));
+                    x = 0;
+                }
+
+                if cursor == Some(offset) {
+                    new_cursor = Position::new(x, rows.len() as u32);
+                }
+
+                if let Some(slot) = row.cells.get_mut(x as usize + 1).filter(|_| cell.wide) {
+                    *slot = Cell {
+                        wide_spacer: true,
+                        ..Cell::new(' ', cell.style)
+                    };
+                }
+
+                if let Some(slot) = row.cells.get_mut(x as usize) {
+                    *slot = cell;
+                }
+
+                x += width;
+            }
+
+            rows.push(row);
+        }
+
+        // Rows above the cursor that don't fit go to history, while the cursor's row stays on
+        // screen and anything that still doesn't fit below it is cut off.
+        let height = size.y as usize;
+        let start = rows.len().saturating_sub(height).min(new_cursor.y as usize);
+
+        let mut screen = rows.split_off(start);
+
+        screen.resize(height, Row::new(size.x, Cell::default()));
+
+        self.size = size;
+        self.rows = screen;
+        self.scrollback = rows.into();
+        self.display_offset = 0;
+        self.trim_scrollback();
+
+        Position::new(new_cursor.x, new_cursor.y.saturating_sub(start as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(columns: u32, lines: &[&str]) -> Grid {
+        let mut grid = Grid::new(Position::new(columns, lines.len() as u32), 100);
+
+        for (y, line) in lines.iter().enumerate() {
+            let row = grid.row_mut(y as u32).unwrap();
+
+            for (cell, character) in row.cells.iter_mut().zip(line.chars()) {
+                cell.character = character;
+            }
+        }
+
+        grid
+    }
+
+    fn screen(grid: &Grid) -> Vec<String> {
+        grid.viewport()
+            .map(|row| {
+                let text = row.cells.iter().map(Cell::text).collect::<String>();
+
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reflow_wrapped_lines() {
+        let mut grid = grid(10, &["abcdefghij", "kl", "xy"]);
+
+        grid.row_mut(0).unwrap().wrapped = true;
+
+        let cursor = grid.resize(Position::new(20, 3), Position::new(2, 2));
+
+        assert_eq!(screen(&grid), ["abcdefghijkl", "xy", ""]);
+        assert_eq!(cursor, Position::new(2, 1));
+
+        let cursor = grid.resize(Position::new(5, 3), cursor);
+
+        assert_eq!(screen(&grid), ["fghij", "kl", "xy"]);
+        assert_eq!(grid.scrollback_len(), 1);
+        assert_eq!(cursor, Position::new(2, 2));
+
+        let cursor = grid.resize(Position::new(6, 4), cursor);
+
+        assert_eq!(screen(&grid), ["abcdef", "ghijkl", "xy", ""]);
+        assert_eq!(grid.scrollback_len(), 0);
+        assert_eq!(cursor, Position::new(2, 2));
+    }
+
+    #[test]
+    fn push_rows_above_cursor_into_scrollback() {
+        let mut grid = grid(10, &["a", "b", "c", "d"]);
+
+        let cursor = grid.resize(Position::new(10, 2), Position::new(1, 2));
+
+        assert_eq!(screen(&grid), ["c", "d"]);
+        assert_eq!(grid.scrollback_len(), 2);
+        assert_eq!(cursor, Position::new(1, 0));
+    }
+
+    #[test]
+    fn truncate_rows_below_cursor() {
+        let mut grid = grid(10, &["a", "b", "c", "d"]);
+
+        let cursor = grid.resize(Position::new(10, 2), Position::new(1, 0));
+
+        assert_eq!(screen(&grid), ["a", "b"]);
+        assert_eq!(grid.scrollback_len(), 0);
+        assert_eq!(cursor, Position::new(1, 0));
+    }
+
+    #[test]
+    fn drop_blank_rows_below_cursor() {
+        let mut grid = grid(10, &["a", "b", "", ""]);
+
+        let cursor = grid.resize(Position::new(10, 2), Position::new(1, 1));
+
+        assert_eq!(screen(&grid), ["a", "b"]);
+        assert_eq!(grid.scrollback_len(), 0);
+        assert_eq!(cursor, Position::new(1, 1));
+    }
+}
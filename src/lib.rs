@@ -1,150 +1,228 @@
-use self::vte::{Vte, VteEvent};
+use self::clipboard::Clipboard;
+use self::convert::{KeyModes, Modifiers};
+use self::grid::{Cell, Grid, Position, TerminalStyle};
+use self::mouse::{MouseAction, MouseMode};
+use self::selection::{Point, Selection, SelectionMode, Span};
+use self::state::{Palette, TerminalState};
+use self::vte::{AnsiColor, UnderlineStyle, Vte, VteEvent};
 use bevy::asset::{embedded_asset, RenderAssetUsages};
-use bevy::color::palettes::basic;
-use bevy::color::Gray;
 use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::{MouseButtonInput, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 use bevy::render::camera::RenderTarget;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
 use bevy::render::view::RenderLayers;
-use compact_str::CompactString;
 use crossbeam_channel::{Receiver, Sender};
 use pseudo_terminal::PseudoTerminal;
 use std::io::{Read, Write};
-use std::process::Command;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus};
+use std::time::Instant;
 use std::{io, mem, thread};
-use vte::{Intensity, NamedColor, StandardColor};
 
+mod charset;
+mod clipboard;
 mod convert;
 mod font;
+mod grid;
+mod mouse;
+mod palette;
 mod pseudo_terminal;
+mod selection;
 mod shell;
+mod state;
+mod terminfo;
 mod vte;
 
-#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
-#[reflect(Component, Debug, Default)]
-#[require(Node)]
-pub struct Terminal;
-
-#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+const FONT_SIZE: f32 = 14.0;
+/// The cell size used until the terminal's font has loaded and been measured.
+const DEFAULT_CELL_SIZE: Vec2 = Vec2::new(10.0, 18.0);
+const DEFAULT_SIZE: UVec2 = UVec2::new(80, 24);
+const WHEEL_LINES: f32 = 3.0;
+const MULTI_CLICK_SECONDS: f32 = 0.4;
+const LONG_PRESS_SECONDS: f32 = 0.5;
+/// A respawned command that exits sooner than this is counted as failing to start.
+const QUICK_EXIT_SECONDS: f32 = 1.0;
+/// How many quick exits in a row a respawned command gets before the terminal holds instead.
+const RESPAWN_LIMIT: u32 = 5;
+
+#[derive(Clone, Copy, Component, Debug, Reflect)]
 #[reflect(Component, Debug, Default)]
-#[require(Node, Text)]
-pub struct TerminalCell;
-
-#[derive(Component, Debug)]
-#[require(Terminal)]
-pub struct TerminalCommand(pub Command);
-
-#[derive(Clone, Debug, Reflect, Component)]
-pub struct TerminalFonts {
-    pub regular: Handle<Font>,
-    pub regular_italic: Handle<Font>,
-    pub bold: Handle<Font>,
-    pub bold_italic: Handle<Font>,
-}
-
-#[derive(Clone, Copy, Debug)]
-struct TerminalStyle {
-    foreground: Color,
-    background: Color,
-    bold: bool,
-    italic: bool,
+#[require(Node, TerminalPalette)]
+pub struct Terminal {
+    pub scrollback_limit: usize,
+    pub exit_policy: ExitPolicy,
+    pub clipboard_access: ClipboardAccess,
+    pub cursor_style: CursorStyle,
+    pub terminfo: Terminfo,
 }
 
-impl Default for TerminalStyle {
+impl Default for Terminal {
     fn default() -> Self {
         Self {
-            foreground: Color::WHITE,
-            background: Color::NONE,
-            bold: false,
-            italic: false,
+            scrollback_limit: 10_000,
+            exit_policy: ExitPolicy::default(),
+            clipboard_access: ClipboardAccess::default(),
+            cursor_style: CursorStyle::default(),
+            terminfo: Terminfo::default(),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-struct TerminalState {
-    cursor_position: UVec2,
-    style: TerminalStyle,
+/// What happens to a terminal once its command exits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Reflect)]
+pub enum ExitPolicy {
+    /// Despawn the terminal.
+    Close,
+    /// Keep the screen and print a "[process exited N]" banner.
+    #[default]
+    Hold,
+    /// Spawn the command again.
+    Respawn,
 }
 
-impl TerminalState {
-    pub fn cursor_position(&self) -> UVec2 {
-        self.cursor_position
-    }
-
-    pub fn cursor_offset(&self, width: u32) -> usize {
-        ((self.cursor_position.y * width) + self.cursor_position.x) as usize
-    }
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Reflect)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
 
-    pub fn move_up(&mut self, rows: u32) {
-        debug_assert!(rows >= 1);
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Reflect)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub blink: bool,
+}
 
-        self.cursor_position.y = self.cursor_position.y.saturating_sub(rows);
+impl Default for CursorStyle {
+    fn default() -> Self {
+        Self {
+            shape: CursorShape::Block,
+            blink: true,
+        }
     }
+}
 
-    pub fn move_down(&mut self, rows: u32) {
-        debug_assert!(rows >= 1);
+impl From<vte::CursorStyle> for CursorStyle {
+    fn from(style: vte::CursorStyle) -> Self {
+        let shape = match style.shape {
+            vte::CursorShape::Block => CursorShape::Block,
+            vte::CursorShape::Underline => CursorShape::Underline,
+            vte::CursorShape::Bar => CursorShape::Bar,
+        };
 
-        self.cursor_position.y = self.cursor_position.y.saturating_add(rows);
+        Self {
+            shape,
+            blink: style.blink,
+        }
     }
+}
 
-    pub fn move_left(&mut self, columns: u32) {
-        debug_assert!(columns >= 1);
-
-        self.cursor_position.x = self.cursor_position.x.saturating_sub(columns);
-    }
+/// How far applications may use the clipboard through OSC 52.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Reflect)]
+pub enum ClipboardAccess {
+    Deny,
+    #[default]
+    WriteOnly,
+    ReadWrite,
+}
 
-    pub fn move_right(&mut self, columns: u32) {
-        debug_assert!(columns >= 1);
+/// Which terminfo entry the command is told to use through `TERM`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Reflect)]
+pub enum Terminfo {
+    /// The bundled `milkshake` entry, installed into `~/.terminfo` on first start.
+    #[default]
+    Milkshake,
+    /// `xterm-256color`, for hosts that lack the `milkshake` entry.
+    Xterm,
+}
 
-        self.cursor_position.x = self.cursor_position.x.saturating_add(columns);
+impl From<UVec2> for Position {
+    fn from(UVec2 { x, y }: UVec2) -> Self {
+        Position::new(x, y)
     }
+}
 
-    pub fn goto(&mut self, position: UVec2) {
-        self.cursor_position = position;
+impl From<Position> for UVec2 {
+    fn from(Position { x, y }: Position) -> Self {
+        UVec2::new(x, y)
     }
+}
 
-    pub fn goto_x(&mut self, x: u32) {
-        self.cursor_position.x = x;
-    }
+/// The terminal that receives keyboard input, set to the one last clicked or touched.
+///
+/// While it's `None`, or names an entity that's no longer a terminal, the first terminal has focus.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct TerminalFocus(pub Option<Entity>);
 
-    pub fn goto_y(&mut self, y: u32) {
-        self.cursor_position.y = y;
-    }
+#[derive(Clone, Copy, Debug, Event)]
+pub struct TerminalExited {
+    pub entity: Entity,
+    pub status: ExitStatus,
+}
 
-    pub fn line_up(&mut self, rows: u32) {
-        self.move_up(rows);
-        self.goto_x(0);
-    }
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, Debug, Default)]
+#[require(Node, Text)]
+pub struct TerminalCell;
 
-    pub fn line_down(&mut self, rows: u32) {
-        self.move_down(rows);
-        self.goto_x(0);
-    }
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, Debug, Default)]
+#[require(Node, Text)]
+pub struct TerminalCursor;
 
-    pub fn style(&self) -> TerminalStyle {
-        self.style
-    }
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, Debug, Default)]
+pub struct TerminalBlink;
 
-    pub fn set_bold(&mut self) {
-        self.style.bold = true;
-    }
+#[derive(Component, Debug)]
+#[require(Terminal)]
+pub struct TerminalCommand(pub Command);
 
-    pub fn set_italic(&mut self) {
-        self.style.italic = true;
-    }
+#[derive(Clone, Debug, Reflect, Component)]
+pub struct TerminalFonts {
+    pub regular: Handle<Font>,
+    pub regular_italic: Handle<Font>,
+    pub bold: Handle<Font>,
+    pub bold_italic: Handle<Font>,
+}
 
-    pub fn reset(&mut self) {
-        mem::take(&mut self.style);
-    }
+#[derive(Clone, Debug, Reflect, Component)]
+#[reflect(Component, Debug, Default)]
+pub struct TerminalPalette {
+    pub colors: [Srgba; 256],
+    pub foreground: Srgba,
+    pub background: Srgba,
+    pub cursor: Srgba,
+    pub selection_foreground: Srgba,
+    pub selection_background: Srgba,
 }
 
 #[derive(Debug, Component)]
 pub struct InternalTerminalState {
     cells: Vec<Entity>,
+    rendered: Vec<Cell>,
+    image_entities: Vec<Entity>,
+    scroll_indicator: Entity,
+    cursor: Entity,
+    scroll_remainder: f32,
+    mouse_cell: Option<UVec2>,
+    last_click: Option<(f32, UVec2, u8)>,
+    selection: Option<Selection>,
+    selecting: bool,
+    long_press: Option<(u64, f32)>,
     pseudo_terminal: PseudoTerminal,
-    writer: Sender<CompactString>,
+    /// The size of a cell in logical pixels, measured from the regular font.
+    cell_size: Vec2,
+    /// The state's palette, converted for rendering whenever it changes.
+    palette: TerminalPalette,
+    child: Option<Child>,
+    spawned_at: Instant,
+    quick_exits: u32,
+    /// A command that couldn't be spawned, reported like an exit on the next update.
+    spawn_failure: Option<ExitStatus>,
+    writer: Sender<Vec<u8>>,
     reader: Receiver<VteEvent>,
     state: TerminalState,
 }
@@ -153,6 +231,10 @@ pub struct TerminalPlugin;
 
 impl Plugin for TerminalPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<TerminalExited>()
+            .init_resource::<Clipboard>()
+            .init_resource::<TerminalFocus>();
+
         embedded_asset!(app, "../assets/fonts/RobotoMono-SemiBold.ttf");
         embedded_asset!(app, "../assets/fonts/RobotoMono-SemiBoldItalic.ttf");
         embedded_asset!(app, "../assets/fonts/RobotoMono-Bold.ttf");
@@ -179,7 +261,19 @@ pub fn main() {
             TerminalPlugin,
         ))
         .add_systems(Startup, setup)
-        .add_systems(Update, (setup_terminal, update, rotate_cubes))
+        .add_systems(
+            Update,
+            (
+                setup_terminal,
+                resize,
+                update,
+                report_mouse,
+                wait.after(update),
+                draw_cursor.after(update),
+                blink,
+                rotate_cubes,
+            ),
+        )
         .run();
 }
 
@@ -199,45 +293,72 @@ fn setup(asset_server: Res<AssetServer>, mut commands: Commands) {
     commands.spawn((
         Node {
             display: Display::Grid,
-            grid_template_columns: RepeatedGridTrack::px(400, 10.0),
-            grid_template_rows: RepeatedGridTrack::px(100, 18.0),
-            height: Val::Percent(1000.0),
-            width: Val::Percent(1000.0),
+            height: Val::Percent(100.0),
+            width: Val::Percent(100.0),
             ..default()
         },
-        Terminal,
+        Terminal::default(),
         TerminalCommand(shell::default()),
         font::default(&asset_server),
+        palette::default(),
     ));
 }
 
-struct Handler(Sender<VteEvent>);
+struct Handler {
+    sender: Sender<VteEvent>,
+    /// Set once the terminal has been despawned and nothing receives events anymore.
+    closed: bool,
+}
 
 impl vte::VteHandler for Handler {
     fn vte_event(&mut self, event: VteEvent) {
-        self.0.send(event).unwrap();
+        self.closed |= self.sender.send(event).is_err();
     }
 }
 
 pub fn setup_terminal(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut TerminalCommand), Without<InternalTerminalState>>,
+    mut query: Query<
+        (Entity, &Terminal, &mut TerminalCommand, &mut Node),
+        Without<InternalTerminalState>,
+    >,
 ) {
-    for (entity, mut command) in query.iter_mut() {
-        let mut pseudo_terminal = PseudoTerminal::new(UVec2::new(400, 100)).unwrap();
+    for (entity, terminal, mut command, mut node) in query.iter_mut() {
+        let size = DEFAULT_SIZE;
+
+        let cell_size = DEFAULT_CELL_SIZE;
+
+        set_grid_tracks(&mut node, size, cell_size);
 
-        pseudo_terminal.configure_command(&mut command.0).unwrap();
+        let mut pseudo_terminal = PseudoTerminal::new(size, cell_size).unwrap();
 
         let reader = {
             let (sender, receiver) = crossbeam_channel::unbounded::<VteEvent>();
             let mut control = pseudo_terminal.control.clone();
 
             thread::spawn(move || {
-                let mut vte = Vte::new(Handler(sender));
+                let mut vte = Vte::new(Handler {
+                    sender,
+                    closed: false,
+                });
                 let mut buf = [0; 1024];
 
-                while let Ok(amount) = control.read(&mut buf) {
-                    vte.process(&buf[..amount]);
+                loop {
+                    match control.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(amount) => vte.process(&buf[..amount]),
+                        Err(error) => {
+                            debug!("stop reading pseudo terminal: {error}");
+
+                            break;
+                        }
+                    }
+
+                    if vte.handler().closed {
+                        debug!("stop reading pseudo terminal: terminal closed");
+
+                        break;
+                    }
                 }
             });
 
@@ -245,12 +366,12 @@ pub fn setup_terminal(
         };
 
         let writer = {
-            let (sender, receiver) = crossbeam_channel::unbounded::<CompactString>();
+            let (sender, receiver) = crossbeam_channel::unbounded::<Vec<u8>>();
             let mut control = pseudo_terminal.control.clone();
 
             thread::spawn(move || {
-                for text in receiver.iter() {
-                    control.write_all(text.as_bytes())?;
+                for bytes in receiver.iter() {
+                    control.write_all(&bytes)?;
                 }
 
                 io::Result::Ok(())
@@ -259,162 +380,400 @@ pub fn setup_terminal(
             sender
         };
 
-        let result = command.0.spawn();
+        let terminal_name = match terminal.terminfo {
+            Terminfo::Milkshake => match terminfo::install() {
+                Ok(()) => terminfo::NAME,
+                Err(error) => {
+                    error!(
+                        "unable to install terminfo, using {}: {error}",
+                        terminfo::FALLBACK_NAME
+                    );
+
+                    terminfo::FALLBACK_NAME
+                }
+            },
+            Terminfo::Xterm => terminfo::FALLBACK_NAME,
+        };
+
+        command.0.env("TERM", terminal_name);
 
-        debug!("spawn command: {command:?} {result:?}");
+        let child = pseudo_terminal
+            .configure_command(&mut command.0)
+            .and_then(|_| spawn(&mut command));
 
-        let internal_terminal_state = InternalTerminalState {
-            cells: vec![Entity::PLACEHOLDER; 400 * 100],
+        let scroll_indicator = commands
+            .spawn((
+                BackgroundColor(Color::BLACK.with_alpha(0.75)),
+                Node {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..default()
+                },
+                Text::default(),
+                TextColor(Color::WHITE),
+                TextFont {
+                    font_size: FONT_SIZE,
+                    ..default()
+                },
+            ))
+            .set_parent(entity)
+            .id();
+
+        let cursor = commands
+            .spawn((
+                TerminalCursor,
+                Node {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                ZIndex(1),
+            ))
+            .set_parent(entity)
+            .id();
+
+        let mut internal_terminal_state = InternalTerminalState {
+            cells: vec![Entity::PLACEHOLDER; size.element_product() as usize],
+            rendered: vec![Cell::default(); size.element_product() as usize],
+            image_entities: Vec::new(),
+            scroll_indicator,
+            cursor,
+            scroll_remainder: 0.0,
+            mouse_cell: None,
+            last_click: None,
+            selection: None,
+            selecting: false,
+            long_press: None,
             pseudo_terminal,
+            cell_size,
+            palette: TerminalPalette::default(),
+            child: None,
+            spawned_at: Instant::now(),
+            quick_exits: 0,
+            spawn_failure: None,
             reader,
             writer,
-            state: default(),
+            state: TerminalState::new(size.into(), terminal.scrollback_limit, terminal_name),
         };
 
+        internal_terminal_state.set_child(child);
+
         commands.entity(entity).insert(internal_terminal_state);
     }
 }
 
+fn spawn(command: &mut TerminalCommand) -> io::Result<Child> {
+    let result = command.0.spawn();
+
+    debug!("spawn command: {command:?} {result:?}");
+
+    result
+}
+
+impl InternalTerminalState {
+    fn set_child(&mut self, child: io::Result<Child>) {
+        match child {
+            Ok(child) => {
+                self.child = Some(child);
+                self.spawned_at = Instant::now();
+            }
+            Err(error) => {
+                error!("unable to spawn command: {error}");
+
+                // Report it the way a shell reports a command it can't run.
+                self.child = None;
+                self.spawn_failure = Some(ExitStatus::from_raw(127 << 8));
+            }
+        }
+    }
+}
+
+impl Drop for InternalTerminalState {
+    fn drop(&mut self) {
+        // Don't leave the command running, or a zombie behind, once the terminal is despawned.
+        if let Some(child) = self.child.as_mut() {
+            if let Err(error) = child.kill().and_then(|_| child.wait()) {
+                error!("unable to stop command: {error}");
+            }
+        }
+    }
+}
+
+fn wait(
+    mut commands: Commands,
+    mut exited: EventWriter<TerminalExited>,
+    mut query: Query<(
+        Entity,
+        &Terminal,
+        &mut TerminalCommand,
+        &mut InternalTerminalState,
+    )>,
+) {
+    for (entity, terminal, mut command, mut state) in query.iter_mut() {
+        let spawn_failed = state.spawn_failure.is_some();
+
+        let Some(status) = state.spawn_failure.take().or_else(|| {
+            state.child.as_mut().and_then(|child| {
+                child
+                    .try_wait()
+                    .inspect_err(|error| error!("unable to wait for command: {error}"))
+                    .ok()
+                    .flatten()
+            })
+        }) else {
+            continue;
+        };
+
+        debug!("command exited: {status}");
+
+        exited.send(TerminalExited { entity, status });
+
+        if state.spawned_at.elapsed().as_secs_f32() < QUICK_EXIT_SECONDS {
+            state.quick_exits += 1;
+        } else {
+            state.quick_exits = 0;
+        }
+
+        match terminal.exit_policy {
+            ExitPolicy::Close => {
+                commands.entity(entity).despawn_recursive();
+            }
+            // Respawning a command that couldn't be spawned would only fail again, and one that
+            // keeps exiting straight away would be respawned every frame.
+            ExitPolicy::Respawn if !spawn_failed && state.quick_exits < RESPAWN_LIMIT => {
+                state.state.reset_modes();
+
+                let child = spawn(&mut command);
+
+                state.set_child(child);
+            }
+            ExitPolicy::Hold | ExitPolicy::Respawn => {
+                let code = status
+                    .code()
+                    .or_else(|| status.signal().map(|signal| 128 + signal))
+                    .unwrap_or_default();
+
+                state.child = None;
+
+                let state = &mut state.state;
+
+                state.reset();
+                state.reset_display();
+
+                if state.cursor_position().x > 0 {
+                    state.next_line();
+                }
+
+                for character in format!("[process exited {code}]").chars() {
+                    state.echo(character);
+                }
+            }
+        }
+    }
+}
+
+fn resize(
+    mut commands: Commands,
+    fonts: Res<Assets<Font>>,
+    mut font_events: EventReader<AssetEvent<Font>>,
+    mut query: Query<(
+        Ref<ComputedNode>,
+        Ref<TerminalFonts>,
+        &mut Node,
+        &mut InternalTerminalState,
+    )>,
+) {
+    let fonts_changed = font_events.read().count() > 0;
+
+    for (computed_node, terminal_fonts, mut node, mut state) in query.iter_mut() {
+        let InternalTerminalState {
+            cells,
+            rendered,
+            selection,
+            pseudo_terminal,
+            cell_size,
+            state,
+            ..
+        } = &mut *state;
+
+        let measured = (fonts_changed || terminal_fonts.is_changed())
+            .then(|| font::cell_size(&fonts, &terminal_fonts, FONT_SIZE))
+            .flatten()
+            .filter(|measured| measured != cell_size);
+
+        if let Some(measured) = measured {
+            debug!("measure cell: {measured}");
+
+            *cell_size = measured;
+        } else if !computed_node.is_changed() {
+            continue;
+        }
+
+        let size = (computed_node.size() * computed_node.inverse_scale_factor() / *cell_size)
+            .floor()
+            .as_uvec2()
+            .max(UVec2::ONE);
+
+        let resized = size != UVec2::from(state.grid().size());
+
+        if !resized && measured.is_none() {
+            continue;
+        }
+
+        debug!("resize terminal: {size}");
+
+        if let Err(error) = pseudo_terminal.resize(size, *cell_size) {
+            error!("unable to resize pseudo terminal: {error}");
+        }
+
+        set_grid_tracks(&mut node, size, *cell_size);
+
+        if !resized {
+            continue;
+        }
+
+        state.resize(size.into());
+
+        *selection = None;
+
+        for cell_entity in cells.drain(..) {
+            if cell_entity != Entity::PLACEHOLDER {
+                commands.entity(cell_entity).despawn_recursive();
+            }
+        }
+
+        cells.resize(size.element_product() as usize, Entity::PLACEHOLDER);
+        rendered.clear();
+        rendered.resize(size.element_product() as usize, Cell::default());
+    }
+}
+
+fn set_grid_tracks(node: &mut Node, size: UVec2, cell_size: Vec2) {
+    node.grid_template_columns = RepeatedGridTrack::px(size.x as u16, cell_size.x);
+    node.grid_template_rows = RepeatedGridTrack::px(size.y as u16, cell_size.y);
+}
+
 #[derive(Clone, Copy, Component, Debug, Default, Eq, PartialEq, Reflect)]
 pub struct Cube;
 
-static TABLE: [Srgba; 8] = [
-    basic::BLACK,
-    basic::RED,
-    basic::GREEN,
-    basic::YELLOW,
-    basic::BLUE,
-    basic::FUCHSIA,
-    basic::AQUA,
-    basic::WHITE,
-];
-
+type TerminalQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Ref<'static, Terminal>,
+        Ref<'static, TerminalPalette>,
+        &'static ComputedNode,
+        &'static GlobalTransform,
+        &'static mut BackgroundColor,
+        &'static mut InternalTerminalState,
+    ),
+    Without<TerminalCell>,
+>;
+
+type CursorQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut BackgroundColor,
+        &'static mut BorderColor,
+        &'static mut Node,
+        &'static mut Text,
+        &'static mut TextColor,
+        &'static mut TextFont,
+    ),
+    (With<TerminalCursor>, Without<TerminalCell>),
+>;
+
+type CellQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut BackgroundColor,
+        &'static mut BorderColor,
+        &'static mut Node,
+        &'static mut Text,
+        &'static mut TextColor,
+        &'static mut TextFont,
+    ),
+    With<TerminalCell>,
+>;
+
+#[allow(clippy::too_many_arguments)]
 fn update(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut keyboard_input: EventReader<KeyboardInput>,
-    mut query: Query<(Entity, &mut InternalTerminalState)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut clipboard: ResMut<Clipboard>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    focus: Res<TerminalFocus>,
+    windows: Query<&Window>,
+    mut query: TerminalQuery,
     terminal_fonts: Query<&TerminalFonts>,
     touch_input: Res<Touches>,
-    mut cell_query: Query<(
-        &mut BackgroundColor,
-        &mut Text,
-        &mut TextColor,
-        &mut TextFont,
-    )>,
+    mut cell_query: CellQuery,
+    mut indicator_query: Query<(&mut Node, &mut Text), Without<TerminalCell>>,
 ) {
-    for (entity, mut state) in query.iter_mut() {
+    let keyboard_input = keyboard_input.read().collect::<Vec<_>>();
+
+    let mouse_wheel = mouse_wheel
+        .read()
+        .filter_map(|event| {
+            let window = windows.get(event.window).ok()?;
+
+            Some((event, window.cursor_position()?))
+        })
+        .collect::<Vec<_>>();
+
+    let focused = focus
+        .0
+        .filter(|entity| query.contains(*entity))
+        .or_else(|| query.iter().next().map(|(entity, ..)| entity));
+
+    for (
+        entity,
+        terminal,
+        terminal_palette,
+        computed_node,
+        transform,
+        mut background_color,
+        mut state,
+    ) in query.iter_mut()
+    {
         let InternalTerminalState {
             cells,
+            rendered,
+            image_entities,
+            scroll_indicator,
+            scroll_remainder,
+            selection,
+            cell_size,
+            palette,
             reader,
             writer,
             state,
             ..
         } = &mut *state;
 
-        for event in reader.try_iter() {
-            debug!("process vte event: {event:?}");
-
-            match event {
-                VteEvent::Echo(character) => {
-                    let Some(cell_entity) = cells.get_mut(state.cursor_offset(400)) else {
-                        continue;
-                    };
-
-                    if *cell_entity == Entity::PLACEHOLDER {
-                        commands.entity(entity).with_children(|builder| {
-                            let terminal_fonts =
-                                terminal_fonts.get(builder.parent_entity()).unwrap();
-
-                            *cell_entity = new_cell(state, terminal_fonts, builder, character);
-                        });
-                    } else {
-                        let terminal_fonts = terminal_fonts.get(entity).unwrap();
-
-                        set_cell(
-                            state,
-                            terminal_fonts,
-                            character,
-                            *cell_entity,
-                            &mut cell_query,
-                        );
-                    }
-
-                    state.move_right(1);
-                }
-                VteEvent::Backspace => {
-                    state.move_left(1);
-
-                    let Some(entity) = cells.get_mut(state.cursor_offset(400)) else {
-                        continue;
-                    };
-
-                    let entity = mem::replace(entity, Entity::PLACEHOLDER);
-
-                    if entity != Entity::PLACEHOLDER {
-                        commands.entity(entity).despawn_recursive();
-                    }
-                }
-
-                VteEvent::Goto(new_position) => state.goto(new_position),
-                VteEvent::GotoX(x) => state.goto_x(x),
-                VteEvent::GotoY(y) => state.goto_y(y),
-
-                VteEvent::LineUp(rows) => state.line_up(rows),
-                VteEvent::LineDown(rows) => state.line_down(rows),
-
-                VteEvent::MoveUp(rows) => state.move_up(rows),
-                VteEvent::MoveDown(rows) => state.move_down(rows),
-                VteEvent::MoveLeft(columns) => state.move_left(columns),
-                VteEvent::MoveRight(columns) => state.move_right(columns),
-
-                VteEvent::Reset => state.reset(),
-                VteEvent::Bold => state.set_bold(),
-                VteEvent::Italic => state.set_italic(),
-                VteEvent::Foreground(color) => {
-                    info!("{color:?}");
-                    if let vte::AnsiColor::Standard(StandardColor { color, intensity }) = color {
-                        let mut color = if color == NamedColor::Black {
-                            Srgba::gray(0.5)
-                        } else {
-                            TABLE[color as usize]
-                        };
+        if terminal.is_changed() {
+            state.set_scrollback_limit(terminal.scrollback_limit);
+        }
 
-                        match intensity {
-                            Intensity::Dim => color = color.darker(0.1),
-                            Intensity::Bright => color = color.lighter(0.1),
-                            _ => {}
-                        };
+        if terminal_palette.is_changed() {
+            state.set_base_palette(Palette::from(&*terminal_palette));
+        }
 
-                        state.style.foreground = color.into();
-                    }
-                }
-                VteEvent::ResetForeground => {
-                    state.style.foreground = TerminalStyle::default().foreground;
-                }
-                VteEvent::Background(color) => {
-                    info!("{color:?}");
-                    if let vte::AnsiColor::Standard(StandardColor { color, intensity }) = color {
-                        let mut color = if color == NamedColor::Black {
-                            Srgba::gray(0.5)
-                        } else {
-                            TABLE[color as usize]
-                        };
+        let alternative_buffer = state.alternative_buffer();
 
-                        match intensity {
-                            Intensity::Dim => color = color.darker(0.1),
-                            Intensity::Bright => color = color.lighter(0.1),
-                            _ => {}
-                        };
+        for event in reader.try_iter() {
+            debug!("process vte event: {event:?}");
 
-                        state.style.background = color.into();
-                    }
-                }
-                VteEvent::ResetBackground => {
-                    state.style.foreground = TerminalStyle::default().foreground;
-                }
+            match &event {
                 VteEvent::Image(image) => {
                     let image =
                         base64::Engine::decode(&base64::engine::general_purpose::STANDARD, image)
@@ -432,36 +791,9 @@ fn update(
                         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
                     ));
 
-                    let (x, y) = state.cursor_position().into();
-
-                    for i in x..(x + 10) {
-                        for j in y..(y + 10) {
-                            let Some(entity) = cells.get_mut(((j * 400) + i) as usize) else {
-                                continue;
-                            };
-
-                            let entity = mem::replace(entity, Entity::PLACEHOLDER);
-
-                            if entity != Entity::PLACEHOLDER {
-                                commands.entity(entity).despawn_recursive();
-                            }
-                        }
-                    }
-
-                    let Some(cell_entity) = cells.get_mut(state.cursor_offset(400)) else {
-                        continue;
-                    };
-
-                    {
-                        let entity = mem::replace(cell_entity, Entity::PLACEHOLDER);
-
-                        if entity != Entity::PLACEHOLDER {
-                            commands.entity(entity).despawn_recursive();
-                        }
-                    }
-
                     commands.entity(entity).with_children(|builder| {
-                        let [grid_column, grid_row] = (state.cursor_position() + UVec2::ONE)
+                        let [grid_column, grid_row] = (UVec2::from(state.cursor_position())
+                            + UVec2::ONE)
                             .to_array()
                             .map(|axis| GridPlacement::start(axis as i16));
 
@@ -471,7 +803,7 @@ fn update(
                             depth_or_array_layers: 1,
                         };
 
-                        *cell_entity = builder
+                        let image_entity = builder
                             .spawn((
                                 //BackgroundColor(state.style.background),
                                 Node {
@@ -530,205 +862,750 @@ fn update(
                                 builder.spawn(UiImage::new(image_handle));
                             })
                             .id();
-                    });
 
-                    state.move_right(10);
+                        image_entities.push(image_entity);
+                    });
                 }
-                VteEvent::ReportCursorPosition => {
-                    let (column, row) = (state.cursor_position() + UVec2::ONE).into();
+                VteEvent::SetClipboard(text) => {
+                    if terminal.clipboard_access == ClipboardAccess::Deny {
+                        debug!("deny clipboard write");
+                    } else {
+                        clipboard.set(text.to_string());
+                    }
+                }
+                VteEvent::QueryClipboard { selection, bell } => {
+                    if terminal.clipboard_access == ClipboardAccess::ReadWrite {
+                        let data = base64::Engine::encode(
+                            &base64::engine::general_purpose::STANDARD,
+                            clipboard.get(),
+                        );
 
-                    writer.send(format!("\x1b[{row};{column}R").into()).unwrap();
+                        let terminator = if *bell { "\x07" } else { "\x1b\\" };
+
+                        writer
+                            .send(format!("\x1b]52;{selection};{data}{terminator}").into_bytes())
+                            .unwrap();
+                    } else {
+                        debug!("deny clipboard read");
+                    }
+                }
+                VteEvent::ClearAll | VteEvent::ClearEverything => {
+                    for image_entity in image_entities.drain(..) {
+                        commands.entity(image_entity).despawn_recursive();
+                    }
                 }
-                VteEvent::ClearLeft => {
-                    let (x, y) = state.cursor_position().into();
+                _ => {}
+            }
 
-                    for i in 0..x {
-                        let Some(entity) = cells.get_mut(((y * 400) + i) as usize) else {
-                            continue;
-                        };
+            state.handle(event);
+        }
 
-                        let entity = mem::replace(entity, Entity::PLACEHOLDER);
+        for reply in state.take_replies() {
+            writer.send(reply.into_string().into_bytes()).unwrap();
+        }
 
-                        if entity != Entity::PLACEHOLDER {
-                            commands.entity(entity).despawn_recursive();
-                        }
+        if state.alternative_buffer() != alternative_buffer {
+            *selection = None;
+        }
+
+        let palette_changed = state.take_palette_changed();
+
+        if palette_changed {
+            *palette = TerminalPalette::from(state.palette());
+            background_color.0 = palette.background.into();
+        }
+
+        let rect = logical_rect(computed_node, transform);
+
+        for (event, _) in mouse_wheel
+            .iter()
+            .filter(|(_, position)| rect.contains(*position))
+        {
+            *scroll_remainder += match event.unit {
+                MouseScrollUnit::Line => event.y * WHEEL_LINES,
+                MouseScrollUnit::Pixel => event.y / cell_size.y,
+            };
+        }
+
+        for touch in touch_input
+            .iter()
+            .filter(|touch| rect.contains(touch.start_position()))
+        {
+            *scroll_remainder += touch.delta().y / cell_size.y;
+        }
+
+        let lines = scroll_remainder.trunc();
+
+        *scroll_remainder -= lines;
+
+        if lines != 0.0 && state.mouse_mode() == MouseMode::None {
+            state.scroll_display(lines as isize);
+        }
+
+        let terminal_fonts = terminal_fonts.get(entity).unwrap();
+
+        render(
+            &mut commands,
+            entity,
+            state.grid(),
+            terminal_fonts,
+            palette,
+            palette_changed,
+            selection.and_then(|selection| selection.span(state.grid())),
+            cells,
+            rendered,
+            &mut cell_query,
+        );
+
+        if let Ok((mut node, mut text)) = indicator_query.get_mut(*scroll_indicator) {
+            let grid = state.grid();
+
+            if grid.display_offset() == 0 {
+                node.display = Display::None;
+            } else {
+                node.display = Display::Flex;
+                text.0 = format!("[{}/{}]", grid.display_offset(), grid.scrollback_len());
+            }
+        }
+
+        if touch_input.any_just_pressed() {
+            debug!("process touch event: {touch_input:?}");
+
+            #[cfg(target_os = "android")]
+            bevy::window::ANDROID_APP
+                .get()
+                .unwrap()
+                .show_soft_input(true);
+        }
+
+        if focused != Some(entity) {
+            continue;
+        }
+
+        let modifiers = Modifiers::new(&keys);
+
+        for event in keyboard_input.iter().copied() {
+            debug!("process keyboard event: {event:?}");
+
+            let copy = event.key_code == KeyCode::Copy
+                || (modifiers.control && modifiers.shift && event.key_code == KeyCode::KeyC);
+
+            if copy {
+                if event.state.is_pressed() {
+                    if let Some(span) = selection.and_then(|selection| selection.span(state.grid()))
+                    {
+                        clipboard.set(span.text(state.grid()));
                     }
                 }
-                VteEvent::ClearRight => {
-                    let (x, y) = state.cursor_position().into();
 
-                    for i in x..400 {
-                        let Some(entity) = cells.get_mut(((y * 400) + i) as usize) else {
-                            continue;
-                        };
+                continue;
+            }
 
-                        let entity = mem::replace(entity, Entity::PLACEHOLDER);
+            let paste = event.key_code == KeyCode::Paste
+                || (modifiers.control && modifiers.shift && event.key_code == KeyCode::KeyV);
 
-                        if entity != Entity::PLACEHOLDER {
-                            commands.entity(entity).despawn_recursive();
-                        }
-                    }
+            if paste {
+                if event.state.is_pressed() {
+                    self::paste(&mut clipboard, state, writer);
                 }
-                VteEvent::ClearLine => {
-                    let (_x, y) = state.cursor_position().into();
 
-                    for i in 0..400 {
-                        let Some(entity) = cells.get_mut(((y * 400) + i) as usize) else {
-                            continue;
-                        };
+                continue;
+            }
 
-                        let entity = mem::replace(entity, Entity::PLACEHOLDER);
+            let modes = KeyModes {
+                application_cursor: state.application_cursor(),
+                application_keypad: state.application_keypad(),
+                keyboard_flags: state.keyboard_flags(),
+            };
 
-                        if entity != Entity::PLACEHOLDER {
-                            commands.entity(entity).despawn_recursive();
-                        }
-                    }
+            if let Some(string) = convert::convert_key(event, modifiers, modes) {
+                state.reset_display();
+                writer.send(string.into_string().into_bytes()).unwrap();
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn report_mouse(
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut mouse_button: EventReader<MouseButtonInput>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    touch_input: Res<Touches>,
+    time: Res<Time>,
+    mut clipboard: ResMut<Clipboard>,
+    windows: Query<&Window>,
+    mut focus: ResMut<TerminalFocus>,
+    mut query: Query<(
+        Entity,
+        &ComputedNode,
+        &GlobalTransform,
+        &mut InternalTerminalState,
+    )>,
+) {
+    let cursor_position = |window| {
+        windows
+            .get(window)
+            .ok()
+            .and_then(|window| window.cursor_position())
+    };
+
+    let held = [MouseButton::Left, MouseButton::Middle, MouseButton::Right]
+        .into_iter()
+        .find(|button| buttons.pressed(*button));
+
+    let mut actions = Vec::new();
+
+    for event in cursor_moved.read() {
+        actions.push((MouseAction::Move(held), Some(event.position)));
+    }
+
+    for event in mouse_button.read() {
+        let action = if event.state.is_pressed() {
+            MouseAction::Press(event.button)
+        } else {
+            MouseAction::Release(event.button)
+        };
+
+        actions.push((action, cursor_position(event.window)));
+    }
+
+    for event in mouse_wheel.read() {
+        let action = if event.y > 0.0 {
+            MouseAction::WheelUp
+        } else if event.y < 0.0 {
+            MouseAction::WheelDown
+        } else {
+            continue;
+        };
+
+        actions.push((action, cursor_position(event.window)));
+    }
+
+    for touch in touch_input.iter_just_pressed() {
+        actions.push((
+            MouseAction::Press(MouseButton::Left),
+            Some(touch.position()),
+        ));
+    }
+
+    for touch in touch_input.iter_just_released() {
+        actions.push((
+            MouseAction::Release(MouseButton::Left),
+            Some(touch.position()),
+        ));
+    }
+
+    let modifiers = Modifiers::new(&keys);
+
+    for (entity, computed_node, transform, mut state) in query.iter_mut() {
+        let InternalTerminalState {
+            mouse_cell,
+            last_click,
+            selection,
+            selecting,
+            long_press,
+            cell_size,
+            writer,
+            state,
+            ..
+        } = &mut *state;
+
+        let now = time.elapsed_secs();
+
+        for touch in touch_input.iter_just_pressed() {
+            *long_press = Some((touch.id(), now));
+        }
+
+        if let Some((id, at)) = *long_press {
+            match touch_input.get_pressed(id) {
+                Some(touch) if touch.distance().length() > cell_size.x => *long_press = None,
+                Some(_) if now - at >= LONG_PRESS_SECONDS => {
+                    *long_press = None;
+
+                    paste(&mut clipboard, state, writer);
                 }
-                VteEvent::ClearUp => {
-                    let (x, y) = state.cursor_position().into();
+                Some(_) => {}
+                None => *long_press = None,
+            }
+        }
 
-                    for i in 0..x {
-                        for j in 0..y {
-                            let Some(entity) = cells.get_mut(((j * 400) + i) as usize) else {
-                                continue;
-                            };
+        let reporting = state.mouse_mode() != MouseMode::None && !modifiers.shift;
 
-                            let entity = mem::replace(entity, Entity::PLACEHOLDER);
+        let rect = logical_rect(computed_node, transform);
 
-                            if entity != Entity::PLACEHOLDER {
-                                commands.entity(entity).despawn_recursive();
-                            }
-                        }
-                    }
+        for (action, position) in actions.iter().copied() {
+            let Some(position) = position else {
+                continue;
+            };
+
+            let position = position - rect.min;
+
+            if position.cmplt(Vec2::ZERO).any() || position.cmpge(rect.size()).any() {
+                continue;
+            }
+
+            if let MouseAction::Press(_) = action {
+                focus.0 = Some(entity);
+            }
+
+            let cell = (position / *cell_size)
+                .as_uvec2()
+                .min(UVec2::from(state.grid().size()) - UVec2::ONE);
+
+            if let MouseAction::Move(_) = action {
+                if *mouse_cell == Some(cell) {
+                    continue;
                 }
-                VteEvent::ClearDown => {
-                    let (x, y) = state.cursor_position().into();
 
-                    for i in x..400 {
-                        for j in y..400 {
-                            let Some(entity) = cells.get_mut(((j * 400) + i) as usize) else {
-                                continue;
-                            };
+                *mouse_cell = Some(cell);
+            }
 
-                            let entity = mem::replace(entity, Entity::PLACEHOLDER);
+            if reporting {
+                let mode = state.mouse_mode();
+                let encoding = state.mouse_encoding();
 
-                            if entity != Entity::PLACEHOLDER {
-                                commands.entity(entity).despawn_recursive();
-                            }
-                        }
-                    }
+                if let Some(report) = mouse::encode(mode, encoding, action, cell, modifiers) {
+                    writer.send(report).unwrap();
                 }
-                VteEvent::ClearAll | VteEvent::ClearEverything => {
-                    for i in 0..400 {
-                        for j in 0..400 {
-                            let Some(entity) = cells.get_mut(((j * 400) + i) as usize) else {
-                                continue;
-                            };
 
-                            let entity = mem::replace(entity, Entity::PLACEHOLDER);
+                continue;
+            }
 
-                            if entity != Entity::PLACEHOLDER {
-                                commands.entity(entity).despawn_recursive();
-                            }
+            let point = Point {
+                line: state.grid().viewport_start() + cell.y as usize,
+                column: cell.x,
+            };
+
+            match action {
+                MouseAction::Press(MouseButton::Left) => {
+                    let count = match *last_click {
+                        Some((at, last_cell, count))
+                            if last_cell == cell && now - at < MULTI_CLICK_SECONDS =>
+                        {
+                            (count % 3) + 1
                         }
+                        _ => 1,
+                    };
+
+                    let mode = match count {
+                        2 => SelectionMode::Word,
+                        3 => SelectionMode::Line,
+                        _ if modifiers.alt => SelectionMode::Block,
+                        _ => SelectionMode::Simple,
+                    };
+
+                    *last_click = Some((now, cell, count));
+                    *selection = Some(Selection::new(mode, point));
+                    *selecting = true;
+                }
+                MouseAction::Move(Some(MouseButton::Left)) if *selecting => {
+                    if let Some(selection) = selection {
+                        selection.update(point);
                     }
                 }
+                MouseAction::Release(MouseButton::Left) => *selecting = false,
+                MouseAction::Press(MouseButton::Middle) => paste(&mut clipboard, state, writer),
                 _ => {}
             }
         }
+    }
+}
 
-        if touch_input.any_just_pressed() {
-            debug!("process touch event: {touch_input:?}");
+/// The area a terminal's node covers, in the logical pixels window cursor positions use.
+fn logical_rect(computed_node: &ComputedNode, transform: &GlobalTransform) -> Rect {
+    let scale = computed_node.inverse_scale_factor();
 
-            #[cfg(target_os = "android")]
-            bevy::window::ANDROID_APP
-                .get()
-                .unwrap()
-                .show_soft_input(true);
-        }
+    Rect::from_center_size(
+        transform.translation().truncate() * scale,
+        computed_node.size() * scale,
+    )
+}
 
-        for event in keyboard_input.read() {
-            debug!("process keyboard event: {event:?}");
+#[allow(clippy::too_many_arguments)]
+fn render(
+    commands: &mut Commands,
+    entity: Entity,
+    grid: &Grid,
+    terminal_fonts: &TerminalFonts,
+    palette: &TerminalPalette,
+    force: bool,
+    selection: Option<Span>,
+    cells: &mut [Entity],
+    rendered: &mut [Cell],
+    cell_query: &mut CellQuery,
+) {
+    let columns = grid.size().x as usize;
+
+    for (y, row) in grid.viewport().enumerate() {
+        let line = grid.viewport_start() + y;
+
+        for (x, cell) in row.cells.iter().enumerate() {
+            let point = Point {
+                line,
+                column: x as u32,
+            };
+
+            let cell = &match selection {
+                Some(span) if span.contains(point) => highlight(palette, cell.clone()),
+                _ => cell.clone(),
+            };
+
+            let index = (y * columns) + x;
+
+            if rendered[index] == *cell && !force {
+                continue;
+            }
+
+            let previous = mem::replace(&mut rendered[index], cell.clone());
+
+            let cell_entity = &mut cells[index];
 
-            if !event.state.is_pressed() {
+            let respawn = (cell.style.strikethrough || previous.style.strikethrough)
+                && cell.style != previous.style;
+
+            if (cell.is_blank() || cell.wide_spacer || respawn)
+                && *cell_entity != Entity::PLACEHOLDER
+            {
+                commands.entity(*cell_entity).despawn_recursive();
+                *cell_entity = Entity::PLACEHOLDER;
+            }
+
+            if cell.is_blank() || cell.wide_spacer {
                 continue;
             }
 
-            if let Some(string) = convert::convert_key(&event.logical_key) {
-                writer.send(string).unwrap();
+            let position = UVec2::new(x as u32, y as u32);
+
+            if *cell_entity == Entity::PLACEHOLDER {
+                *cell_entity =
+                    spawn_cell(commands, entity, position, cell, terminal_fonts, palette);
+            } else {
+                set_cell(
+                    position,
+                    cell,
+                    terminal_fonts,
+                    palette,
+                    *cell_entity,
+                    cell_query,
+                );
+
+                if cell.style.blink != previous.style.blink {
+                    if cell.style.blink {
+                        commands.entity(*cell_entity).insert(TerminalBlink);
+                    } else {
+                        commands.entity(*cell_entity).remove::<TerminalBlink>();
+                    }
+                }
             }
         }
     }
 }
 
-fn new_cell(
-    terminal_state: &mut TerminalState,
-    terminal_fonts: &TerminalFonts,
-    builder: &mut ChildBuilder<'_>,
-    character: char,
-) -> Entity {
-    let [grid_column, grid_row] = (terminal_state.cursor_position() + UVec2::ONE)
-        .to_array()
-        .map(|axis| GridPlacement::start(axis as i16));
+fn paste(clipboard: &mut Clipboard, state: &mut TerminalState, writer: &Sender<Vec<u8>>) {
+    let text = clipboard.get();
+
+    if text.is_empty() {
+        return;
+    }
+
+    state.reset_display();
+    writer
+        .send(
+            convert::paste(&text, state.bracketed_paste())
+                .into_string()
+                .into_bytes(),
+        )
+        .unwrap();
+}
+
+fn highlight(palette: &TerminalPalette, cell: Cell) -> Cell {
+    let rgb = |color: Srgba| {
+        let [r, g, b] = color.to_u8_array_no_alpha();
+
+        AnsiColor::Rgb(r, g, b)
+    };
+
+    Cell {
+        style: TerminalStyle {
+            foreground: Some(rgb(palette.selection_foreground)),
+            background: Some(rgb(palette.selection_background)),
+            inverse: false,
+            hidden: false,
+            ..cell.style
+        },
+        ..cell
+    }
+}
+
+fn draw_cursor(
+    time: Res<Time>,
+    windows: Query<&Window>,
+    query: Query<(&Terminal, &TerminalFonts, &InternalTerminalState)>,
+    mut cursor_query: CursorQuery,
+) {
+    let focused = windows.iter().any(|window| window.focused);
+    let blink_visible = time.elapsed_secs().fract() < 0.5;
+
+    for (terminal, terminal_fonts, state) in query.iter() {
+        let InternalTerminalState {
+            cursor,
+            cell_size,
+            palette,
+            state,
+            ..
+        } = state;
+
+        let Ok((
+            mut background_color,
+            mut border_color,
+            mut node,
+            mut text,
+            mut text_color,
+            mut font,
+        )) = cursor_query.get_mut(*cursor)
+        else {
+            continue;
+        };
+
+        let grid = state.grid();
+        let style = state
+            .cursor_style()
+            .map(CursorStyle::from)
+            .unwrap_or(terminal.cursor_style);
+        let position =
+            UVec2::from(state.cursor_position()).min(UVec2::from(grid.size()) - UVec2::ONE);
+        let y = position.y + grid.display_offset() as u32;
+
+        let visible = state.cursor_visible()
+            && y < grid.size().y
+            && (blink_visible || !style.blink || !focused);
+
+        if !visible {
+            if node.display != Display::None {
+                node.display = Display::None;
+            }
+
+            continue;
+        }
+
+        let cell = grid
+            .viewport()
+            .nth(y as usize)
+            .and_then(|row| row.cells.get(position.x as usize))
+            .cloned()
+            .unwrap_or_default();
+
+        let width = if cell.wide { 2.0 } else { 1.0 } * cell_size.x;
+        let origin = UVec2::new(position.x, y).as_vec2() * *cell_size;
+
+        let (offset, size) = match style.shape {
+            CursorShape::Underline if focused => {
+                (Vec2::new(0.0, cell_size.y - 2.0), Vec2::new(width, 2.0))
+            }
+            CursorShape::Bar if focused => (Vec2::ZERO, Vec2::new(2.0, cell_size.y)),
+            _ => (Vec2::ZERO, Vec2::new(width, cell_size.y)),
+        };
+
+        let border = if focused {
+            UiRect::ZERO
+        } else {
+            UiRect::all(Val::Px(1.0))
+        };
+
+        node.set_if_neq(Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(origin.x + offset.x),
+            top: Val::Px(origin.y + offset.y),
+            width: Val::Px(size.x),
+            height: Val::Px(size.y),
+            border,
+            ..default()
+        });
+
+        let block = focused && style.shape == CursorShape::Block;
+        let character = if block { cell.text() } else { String::new() };
+
+        let background = if focused {
+            palette.cursor.into()
+        } else {
+            Color::NONE
+        };
+
+        background_color.set_if_neq(BackgroundColor(background));
+        border_color.set_if_neq(BorderColor(palette.cursor.into()));
+
+        if text.0 != character {
+            text.0 = character;
+        }
+
+        let foreground = palette.background.into();
+
+        if text_color.0 != foreground {
+            text_color.0 = foreground;
+        }
+
+        let text_font = text_font(terminal_fonts, cell.style);
+
+        if font.font != text_font.font {
+            *font = text_font;
+        }
+    }
+}
+
+fn blink(time: Res<Time>, mut query: Query<&mut TextColor, With<TerminalBlink>>) {
+    let alpha = if time.elapsed_secs().fract() < 0.5 {
+        1.0
+    } else {
+        0.0
+    };
+
+    for mut text_color in query.iter_mut() {
+        text_color.0.set_alpha(alpha);
+    }
+}
+
+fn colors(palette: &TerminalPalette, style: TerminalStyle) -> (Color, Color) {
+    let resolve = |color| palette.resolve(color);
+    let foreground = style.foreground.map(resolve).unwrap_or(palette.foreground);
+    let background = style.background.map(resolve);
+
+    let (mut foreground, background) = if style.inverse {
+        (background.unwrap_or(palette.background), Some(foreground))
+    } else {
+        (foreground, background)
+    };
+
+    if style.dim {
+        foreground = foreground.darker(0.2);
+    }
+
+    if style.hidden {
+        foreground = Srgba::NONE;
+    }
+
+    (
+        foreground.into(),
+        background.map_or(Color::NONE, Color::from),
+    )
+}
+
+fn border(palette: &TerminalPalette, style: TerminalStyle) -> (UiRect, BorderColor) {
+    let underline = match style.underline {
+        UnderlineStyle::None => 0.0,
+        UnderlineStyle::Double => 2.0,
+        _ => 1.0,
+    };
 
-    let font = match (terminal_state.style.bold, terminal_state.style.italic) {
+    let overline = if style.overline { 1.0 } else { 0.0 };
+
+    let color = match style.underline_color {
+        Some(underline_color) if style.underline != UnderlineStyle::None => {
+            palette.resolve(underline_color).into()
+        }
+        _ => colors(palette, style).0,
+    };
+
+    let rect = UiRect {
+        top: Val::Px(overline),
+        bottom: Val::Px(underline),
+        ..default()
+    };
+
+    (rect, BorderColor(color))
+}
+
+fn grid_column(x: u32, cell: &Cell) -> GridPlacement {
+    let span = if cell.wide { 2 } else { 1 };
+
+    GridPlacement::start_span((x + 1) as i16, span)
+}
+
+fn text_font(terminal_fonts: &TerminalFonts, style: TerminalStyle) -> TextFont {
+    let font = match (style.bold, style.italic) {
         (true, true) => &terminal_fonts.bold_italic,
         (true, false) => &terminal_fonts.bold,
         (false, true) => &terminal_fonts.regular_italic,
         (false, false) => &terminal_fonts.regular,
     };
 
-    builder
-        .spawn((
-            BackgroundColor(terminal_state.style.background),
-            Node {
-                grid_column,
-                grid_row,
-                ..default()
-            },
-            TerminalCell,
-            Text::new(character),
-            TextColor(terminal_state.style.foreground),
-            TextFont {
-                font: font.clone(),
-                font_size: 14.0,
-                ..default()
-            },
-        ))
-        .id()
+    TextFont {
+        font: font.clone(),
+        font_size: FONT_SIZE,
+        ..default()
+    }
+}
+
+fn spawn_cell(
+    commands: &mut Commands,
+    parent: Entity,
+    position: UVec2,
+    cell: &Cell,
+    terminal_fonts: &TerminalFonts,
+    palette: &TerminalPalette,
+) -> Entity {
+    let (foreground, background) = colors(palette, cell.style);
+    let (border, border_color) = border(palette, cell.style);
+
+    let mut cell_commands = commands.spawn((
+        BackgroundColor(background),
+        border_color,
+        Node {
+            grid_column: grid_column(position.x, cell),
+            grid_row: GridPlacement::start((position.y + 1) as i16),
+            border,
+            ..default()
+        },
+        TerminalCell,
+        Text::new(cell.text()),
+        TextColor(foreground),
+        text_font(terminal_fonts, cell.style),
+    ));
+
+    if cell.style.blink {
+        cell_commands.insert(TerminalBlink);
+    }
+
+    if cell.style.strikethrough {
+        cell_commands.with_children(|builder| {
+            builder.spawn((
+                BackgroundColor(foreground),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(50.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Px(1.0),
+                    ..default()
+                },
+            ));
+        });
+    }
+
+    cell_commands.set_parent(parent).id()
 }
 
 fn set_cell(
-    terminal_state: &mut TerminalState,
+    position: UVec2,
+    cell: &Cell,
     terminal_fonts: &TerminalFonts,
-    character: char,
+    palette: &TerminalPalette,
     cell_entity: Entity,
-    cell_query: &mut Query<(
-        &mut BackgroundColor,
-        &mut Text,
-        &mut TextColor,
-        &mut TextFont,
-    )>,
+    cell_query: &mut CellQuery,
 ) {
-    let Ok((mut background_color, mut text, mut text_color, mut text_font)) =
+    let Ok((mut background_color, mut border_color, mut node, mut text, mut text_color, mut font)) =
         cell_query.get_mut(cell_entity)
     else {
         return;
     };
 
-    let font = match (terminal_state.style.bold, terminal_state.style.italic) {
-        (true, true) => &terminal_fonts.bold_italic,
-        (true, false) => &terminal_fonts.bold,
-        (false, true) => &terminal_fonts.regular_italic,
-        (false, false) => &terminal_fonts.regular,
-    };
+    let (foreground, background) = colors(palette, cell.style);
 
-    background_color.0 = terminal_state.style.background;
-    text.0 = character.to_string();
-    text_color.0 = terminal_state.style.foreground;
-    *text_font = TextFont {
-        font: font.clone(),
-        font_size: 14.0,
-        ..default()
-    };
+    background_color.0 = background;
+    (node.border, *border_color) = border(palette, cell.style);
+    node.grid_column = grid_column(position.x, cell);
+    text.0 = cell.text();
+    text_color.0 = foreground;
+    *font = text_font(terminal_fonts, cell.style);
 }
@@ -0,0 +1,242 @@
+use crate::convert::Modifiers;
+use bevy::input::mouse::MouseButton;
+use bevy::math::UVec2;
+
+/// Which mouse events are reported, set with DECSET 9, 1000, 1002 and 1003.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MouseMode {
+    #[default]
+    None,
+    Press,
+    Click,
+    Drag,
+    Motion,
+}
+
+/// How mouse reports are encoded, set with DECSET 1005, 1006 and 1015.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MouseEncoding {
+    #[default]
+    Default,
+    Utf8,
+    Sgr,
+    Urxvt,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    Move(Option<MouseButton>),
+    WheelUp,
+    WheelDown,
+}
+
+pub fn encode(
+    mode: MouseMode,
+    encoding: MouseEncoding,
+    action: MouseAction,
+    position: UVec2,
+    modifiers: Modifiers,
+) -> Option<Vec<u8>> {
+    match (mode, action) {
+        (MouseMode::None, _) => return None,
+        (MouseMode::Press, MouseAction::Press(_)) => {}
+        (MouseMode::Press, _) => return None,
+        (MouseMode::Click, MouseAction::Move(_)) => return None,
+        (MouseMode::Drag, MouseAction::Move(None)) => return None,
+        _ => {}
+    }
+
+    let mut code = match action {
+        MouseAction::Press(button) | MouseAction::Move(Some(button)) => button_code(button)?,
+        MouseAction::Release(button) if encoding == MouseEncoding::Sgr => button_code(button)?,
+        MouseAction::Release(_) | MouseAction::Move(None) => 3,
+        MouseAction::WheelUp => 64,
+        MouseAction::WheelDown => 65,
+    };
+
+    if let MouseAction::Move(_) = action {
+        code += 32;
+    }
+
+    if mode != MouseMode::Press {
+        code += (modifiers.shift as u32 * 4)
+            + (modifiers.alt as u32 * 8)
+            + (modifiers.control as u32 * 16);
+    }
+
+    let (x, y) = (position + UVec2::ONE).into();
+
+    let bytes = match encoding {
+        MouseEncoding::Sgr => {
+            let final_byte = if let MouseAction::Release(_) = action {
+                'm'
+            } else {
+                'M'
+            };
+
+            format!("\x1b[<{code};{x};{y}{final_byte}").into_bytes()
+        }
+        MouseEncoding::Urxvt => format!("\x1b[{};{x};{y}M", code + 32).into_bytes(),
+        MouseEncoding::Utf8 => {
+            let [code, x, y] = [code, x, y].map(|value| char::from_u32(value + 32));
+
+            format!("\x1b[M{}{}{}", code?, x?, y?).into_bytes()
+        }
+        MouseEncoding::Default => {
+            // Each value is a single byte offset by 32, so nothing past column or row 223 fits.
+            let [code, x, y] = [code, x, y].map(|value| u8::try_from(value + 32).ok());
+
+            vec![0x1b, b'[', b'M', code?, x?, y?]
+        }
+    };
+
+    Some(bytes)
+}
+
+fn button_code(button: MouseButton) -> Option<u32> {
+    let code = match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+        MouseButton::Back => 128,
+        MouseButton::Forward => 129,
+        MouseButton::Other(_) => return None,
+    };
+
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: Modifiers = Modifiers {
+        shift: false,
+        alt: false,
+        control: false,
+        super_key: false,
+    };
+
+    const LEFT: MouseButton = MouseButton::Left;
+
+    fn report(
+        mode: MouseMode,
+        encoding: MouseEncoding,
+        action: MouseAction,
+        x: u32,
+        y: u32,
+    ) -> Option<Vec<u8>> {
+        encode(mode, encoding, action, UVec2::new(x, y), NONE)
+    }
+
+    #[test]
+    fn report_events_for_each_mode() {
+        let default = MouseEncoding::Default;
+        let press = MouseAction::Press(LEFT);
+        let release = MouseAction::Release(LEFT);
+        let drag = MouseAction::Move(Some(LEFT));
+        let hover = MouseAction::Move(None);
+
+        assert_eq!(report(MouseMode::None, default, press, 0, 0), None);
+
+        assert_eq!(
+            report(MouseMode::Press, default, press, 0, 0).unwrap(),
+            b"\x1b[M !!"
+        );
+        assert_eq!(report(MouseMode::Press, default, release, 0, 0), None);
+        assert_eq!(report(MouseMode::Press, default, drag, 0, 0), None);
+
+        assert_eq!(
+            report(MouseMode::Click, default, release, 1, 2).unwrap(),
+            b"\x1b[M#\"#"
+        );
+        assert_eq!(report(MouseMode::Click, default, drag, 0, 0), None);
+
+        assert_eq!(
+            report(MouseMode::Drag, default, drag, 0, 0).unwrap(),
+            b"\x1b[M@!!"
+        );
+        assert_eq!(report(MouseMode::Drag, default, hover, 0, 0), None);
+
+        assert_eq!(
+            report(MouseMode::Motion, default, hover, 0, 0).unwrap(),
+            b"\x1b[MC!!"
+        );
+        assert_eq!(
+            report(MouseMode::Motion, default, MouseAction::WheelDown, 0, 0).unwrap(),
+            b"\x1b[Ma!!"
+        );
+    }
+
+    #[test]
+    fn report_modifiers_outside_x10_mode() {
+        let control_shift = Modifiers {
+            shift: true,
+            control: true,
+            ..NONE
+        };
+        let press = MouseAction::Press(MouseButton::Right);
+        let at = UVec2::ZERO;
+
+        assert_eq!(
+            encode(
+                MouseMode::Click,
+                MouseEncoding::Sgr,
+                press,
+                at,
+                control_shift
+            )
+            .unwrap(),
+            b"\x1b[<22;1;1M"
+        );
+        assert_eq!(
+            encode(
+                MouseMode::Press,
+                MouseEncoding::Sgr,
+                press,
+                at,
+                control_shift
+            )
+            .unwrap(),
+            b"\x1b[<2;1;1M"
+        );
+    }
+
+    #[test]
+    fn encode_reports() {
+        let click = MouseMode::Click;
+        let press = MouseAction::Press(LEFT);
+        let release = MouseAction::Release(LEFT);
+
+        assert_eq!(
+            report(click, MouseEncoding::Default, press, 222, 0).unwrap(),
+            b"\x1b[M \xff!"
+        );
+        assert_eq!(report(click, MouseEncoding::Default, press, 223, 0), None);
+
+        assert_eq!(
+            report(click, MouseEncoding::Utf8, press, 299, 0).unwrap(),
+            "\x1b[M \u{14c}!".as_bytes()
+        );
+
+        assert_eq!(
+            report(click, MouseEncoding::Sgr, press, 299, 9).unwrap(),
+            b"\x1b[<0;300;10M"
+        );
+        assert_eq!(
+            report(click, MouseEncoding::Sgr, release, 299, 9).unwrap(),
+            b"\x1b[<0;300;10m"
+        );
+
+        assert_eq!(
+            report(click, MouseEncoding::Urxvt, press, 299, 9).unwrap(),
+            b"\x1b[32;300;10M"
+        );
+        assert_eq!(
+            report(click, MouseEncoding::Urxvt, release, 299, 9).unwrap(),
+            b"\x1b[35;300;10M"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use super::TerminalPalette;
+use crate::state::{Palette, Rgb};
+use crate::vte::{AnsiColor, Intensity, StandardColor};
+use bevy::color::{ColorToPacked, Luminance, Srgba};
+use std::path::Path;
+use std::{env, fs, io};
+
+impl Default for TerminalPalette {
+    fn default() -> Self {
+        Self::from(&Palette::default())
+    }
+}
+
+impl From<&Palette> for TerminalPalette {
+    fn from(palette: &Palette) -> Self {
+        let srgba = |[r, g, b]: Rgb| Srgba::rgb_u8(r, g, b);
+
+        Self {
+            colors: palette.colors.map(srgba),
+            foreground: srgba(palette.foreground),
+            background: srgba(palette.background),
+            cursor: srgba(palette.cursor),
+            selection_foreground: srgba(palette.selection_foreground),
+            selection_background: srgba(palette.selection_background),
+        }
+    }
+}
+
+impl From<&TerminalPalette> for Palette {
+    fn from(palette: &TerminalPalette) -> Self {
+        let rgb = |color: Srgba| color.to_u8_array_no_alpha();
+
+        Self {
+            colors: palette.colors.map(rgb),
+            foreground: rgb(palette.foreground),
+            background: rgb(palette.background),
+            cursor: rgb(palette.cursor),
+            selection_foreground: rgb(palette.selection_foreground),
+            selection_background: rgb(palette.selection_background),
+        }
+    }
+}
+
+impl TerminalPalette {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("itermcolors") => from_itermcolors(&source),
+            _ => from_toml(&source),
+        }
+    }
+
+    pub fn resolve(&self, color: AnsiColor) -> Srgba {
+        match color {
+            AnsiColor::Standard(StandardColor { color, intensity }) => match intensity {
+                Intensity::Normal => self.colors[color as usize],
+                Intensity::Bright => self.colors[color as usize + 8],
+                Intensity::Dim => self.colors[color as usize].darker(0.2),
+            },
+            AnsiColor::Index(index) => self.colors[index as usize],
+            AnsiColor::Rgb(r, g, b) => Srgba::rgb_u8(r, g, b),
+        }
+    }
+}
+
+pub fn default() -> TerminalPalette {
+    let Some(path) = env::var_os("MILKSHAKE_THEME") else {
+        return TerminalPalette::default();
+    };
+
+    TerminalPalette::load(Path::new(&path)).unwrap_or_else(|error| {
+        bevy::prelude::error!("unable to load theme {path:?}: {error}");
+
+        TerminalPalette::default()
+    })
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+fn slot<'a>(palette: &'a mut TerminalPalette, name: &str) -> Option<&'a mut Srgba> {
+    let slot = match name {
+        "foreground" | "Foreground Color" => &mut palette.foreground,
+        "background" | "Background Color" => &mut palette.background,
+        "cursor" | "Cursor Color" => &mut palette.cursor,
+        "selection_foreground" | "Selected Text Color" => &mut palette.selection_foreground,
+        "selection_background" | "Selection Color" => &mut palette.selection_background,
+        name => {
+            let index = name.strip_prefix("Ansi ")?.strip_suffix(" Color")?;
+
+            palette.colors.get_mut(index.parse::<usize>().ok()?)?
+        }
+    };
+
+    Some(slot)
+}
+
+fn from_toml(source: &str) -> io::Result<TerminalPalette> {
+    let table = source.parse::<toml::Table>().map_err(invalid_data)?;
+    let mut palette = TerminalPalette::default();
+
+    let parse = |value: &toml::Value| {
+        let hex = value
+            .as_str()
+            .ok_or_else(|| invalid_data("expected a colour string"))?;
+
+        Srgba::hex(hex).map_err(invalid_data)
+    };
+
+    for (key, value) in &table {
+        if key == "colors" {
+            let colors = value
+                .as_array()
+                .ok_or_else(|| invalid_data("expected an array of colours"))?;
+
+            for (slot, value) in palette.colors.iter_mut().zip(colors) {
+                *slot = parse(value)?;
+            }
+
+            continue;
+        }
+
+        let slot = slot(&mut palette, key)
+            .ok_or_else(|| invalid_data(format!("unknown theme key: {key}")))?;
+
+        *slot = parse(value)?;
+    }
+
+    Ok(palette)
+}
+
+fn from_itermcolors(source: &str) -> io::Result<TerminalPalette> {
+    let mut palette = TerminalPalette::default();
+    let mut name = "";
+    let mut channel = None;
+
+    for (tag, text) in source
+        .split('<')
+        .filter_map(|element| element.split_once('>'))
+    {
+        match (tag, text) {
+            ("key", "Red Component") => channel = Some(0),
+            ("key", "Green Component") => channel = Some(1),
+            ("key", "Blue Component") => channel = Some(2),
+            ("key", text) if text.ends_with(" Color") => {
+                name = text;
+                channel = None;
+            }
+            ("key", _) => channel = None,
+            ("real", text) => {
+                let Some(channel) = channel.take() else {
+                    continue;
+                };
+
+                let Some(slot) = slot(&mut palette, name) else {
+                    continue;
+                };
+
+                let value = text.trim().parse::<f32>().map_err(invalid_data)?;
+
+                match channel {
+                    0 => slot.red = value,
+                    1 => slot.green = value,
+                    _ => slot.blue = value,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(palette)
+}
@@ -1,6 +1,6 @@
-use bevy::math::UVec2;
+use bevy::math::{UVec2, Vec2};
 use rustix::process;
-use rustix::termios::Winsize;
+use rustix::termios::{self, Winsize};
 use std::fs::File;
 use std::io;
 use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd, RawFd};
@@ -15,26 +15,24 @@ pub struct PseudoTerminal {
 }
 
 impl PseudoTerminal {
-    pub fn new(size: UVec2) -> io::Result<PseudoTerminal> {
-        let size = Winsize {
-            ws_col: size.x as u16,
-            ws_row: size.y as u16,
-            ws_xpixel: (size.x * 10) as u16,
-            ws_ypixel: (size.y * 18) as u16,
-        };
-
-        let pty = rustix_openpty::openpty(None, Some(&size))?;
+    pub fn new(size: UVec2, cell_size: Vec2) -> io::Result<PseudoTerminal> {
+        let pty = rustix_openpty::openpty(None, Some(&winsize(size, cell_size)))?;
         let control = Arc::new(File::from(pty.controller));
         let user = pty.user;
 
         Ok(PseudoTerminal { control, user })
     }
 
+    pub fn resize(&self, size: UVec2, cell_size: Vec2) -> io::Result<()> {
+        termios::tcsetwinsize(&*self.control, winsize(size, cell_size))?;
+
+        Ok(())
+    }
+
     pub fn configure_command(&mut self, command: &mut Command) -> io::Result<()> {
         let Self { user, .. } = self;
 
         command
-            .env("TERM", "xterm-256color")
             .env("COLORTERM", "truecolor")
             .stdin(user.try_clone()?)
             .stdout(user.try_clone()?)
@@ -50,6 +48,17 @@ impl PseudoTerminal {
     }
 }
 
+fn winsize(size: UVec2, cell_size: Vec2) -> Winsize {
+    let pixels = (size.as_vec2() * cell_size).as_uvec2();
+
+    Winsize {
+        ws_col: size.x as u16,
+        ws_row: size.y as u16,
+        ws_xpixel: pixels.x as u16,
+        ws_ypixel: pixels.y as u16,
+    }
+}
+
 fn set_controlling_terminal(user: RawFd) -> io::Result<()> {
     process::setsid()?;
     process::ioctl_tiocsctty(unsafe { BorrowedFd::borrow_raw(user) })?;
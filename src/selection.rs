@@ -0,0 +1,182 @@
+use crate::grid::Grid;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SelectionMode {
+    Simple,
+    Word,
+    Line,
+    Block,
+}
+
+/// A cell position, with lines numbered as by [`Grid::viewport_start`].
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Point {
+    pub line: usize,
+    pub column: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Selection {
+    mode: SelectionMode,
+    anchor: Point,
+    head: Point,
+}
+
+impl Selection {
+    pub fn new(mode: SelectionMode, point: Point) -> Self {
+        Self {
+            mode,
+            anchor: point,
+            head: point,
+        }
+    }
+
+    pub fn update(&mut self, point: Point) {
+        self.head = point;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mode == SelectionMode::Simple && self.anchor == self.head
+    }
+
+    /// The selected cells, expanded to whole words or lines.
+    pub fn span(&self, grid: &Grid) -> Option<Span> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut start = self.anchor.min(self.head);
+        let mut end = self.anchor.max(self.head);
+        let last_column = grid.size().x.saturating_sub(1);
+
+        match self.mode {
+            SelectionMode::Simple => {}
+            SelectionMode::Word => {
+                start = word_boundary(grid, start, -1);
+                end = word_boundary(grid, end, 1);
+            }
+            SelectionMode::Line => {
+                while start.line > 0 && grid.line(start.line - 1).is_some_and(|row| row.wrapped) {
+                    start.line -= 1;
+                }
+
+                while grid.line(end.line).is_some_and(|row| row.wrapped) {
+                    end.line += 1;
+                }
+
+                start.column = 0;
+                end.column = last_column;
+            }
+            SelectionMode::Block => {
+                start.column = self.anchor.column.min(self.head.column);
+                end.column = self.anchor.column.max(self.head.column);
+            }
+        }
+
+        Some(Span {
+            start,
+            end,
+            block: self.mode == SelectionMode::Block,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    start: Point,
+    end: Point,
+    block: bool,
+}
+
+impl Span {
+    pub fn contains(&self, point: Point) -> bool {
+        if self.block {
+            (self.start.line..=self.end.line).contains(&point.line)
+                && (self.start.column..=self.end.column).contains(&point.column)
+        } else {
+            (self.start..=self.end).contains(&point)
+        }
+    }
+
+    pub fn text(&self, grid: &Grid) -> String {
+        let mut text = String::new();
+
+        for line in self.start.line..=self.end.line {
+            let Some(row) = grid.line(line) else {
+                continue;
+            };
+
+            let columns = match self.block {
+                true => self.start.column..=self.end.column,
+                false if line == self.start.line && line == self.end.line => {
+                    self.start.column..=self.end.column
+                }
+                false if line == self.start.line => self.start.column..=u32::MAX,
+                false if line == self.end.line => 0..=self.end.column,
+                false => 0..=u32::MAX,
+            };
+
+            let mut tab = 0..0;
+
+            for (cell, x) in row
+                .cells
+                .iter()
+                .zip(0..)
+                .filter(|(cell, x)| columns.contains(x) && !cell.wide_spacer)
+            {
+                if tab.contains(&x) {
+                    continue;
+                }
+
+                if cell.tab > 0 {
+                    tab = x..x + cell.tab;
+                    text.push('\t');
+                    continue;
+                }
+
+                text.push(cell.character);
+                text.extend(&cell.combining);
+            }
+
+            if self.block || !row.wrapped {
+                text.truncate(text.trim_end_matches([' ', '\t']).len());
+
+                if line != self.end.line {
+                    text.push('\n');
+                }
+            }
+        }
+
+        text
+    }
+}
+
+fn is_word(character: char) -> bool {
+    !character.is_whitespace() && !"\"'`()[]{}<>|,;".contains(character)
+}
+
+fn word_boundary(grid: &Grid, mut point: Point, direction: i32) -> Point {
+    let Some(row) = grid.line(point.line) else {
+        return point;
+    };
+
+    let word = |column: u32| {
+        row.cells
+            .get(column as usize)
+            .is_some_and(|cell| is_word(cell.character) || cell.wide_spacer)
+    };
+
+    if !word(point.column) {
+        return point;
+    }
+
+    while let Some(column) = point.column.checked_add_signed(direction) {
+        if !word(column) {
+            break;
+        }
+
+        point.column = column;
+    }
+
+    point
+}
@@ -0,0 +1,1790 @@
+use crate::charset::Charsets;
+use crate::grid::{Cell, Grid, Position, TerminalStyle};
+use crate::mouse::{MouseEncoding, MouseMode};
+use crate::terminfo;
+use crate::vte::{AlternativeBuffer, ColorSlot, UnderlineStyle, VteEvent};
+use crate::vte::{AnsiColor, CursorShape, CursorStyle, Intensity, StandardColor};
+use compact_str::CompactString;
+use std::mem;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+const KEYBOARD_FLAGS_DEPTH: usize = 16;
+const COMBINING_LIMIT: usize = 16;
+
+static ANSI: [u32; 16] = [
+    0x000000, 0xcd0000, 0x00cd00, 0xcdcd00, 0x0000ee, 0xcd00cd, 0x00cdcd, 0xe5e5e5, 0x7f7f7f,
+    0xff0000, 0x00ff00, 0xffff00, 0x5c5cff, 0xff00ff, 0x00ffff, 0xffffff,
+];
+
+pub type Rgb = [u8; 3];
+
+/// The colours applications see and change through OSC 4, 10, 11, 12, 17 and 19.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 256],
+    pub foreground: Rgb,
+    pub background: Rgb,
+    pub cursor: Rgb,
+    pub selection_foreground: Rgb,
+    pub selection_background: Rgb,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            colors: default_colors(),
+            foreground: [255; 3],
+            background: [0; 3],
+            cursor: [255; 3],
+            selection_foreground: [0; 3],
+            selection_background: [191; 3],
+        }
+    }
+}
+
+/// What DECSC (and `CSI s`) saves for DECRC to restore.
+#[derive(Clone, Copy, Debug, Default)]
+struct SavedCursor {
+    position: Position,
+    pending_wrap: bool,
+    style: TerminalStyle,
+    charsets: Charsets,
+}
+
+#[derive(Clone, Debug)]
+pub struct TerminalState {
+    grid: Grid,
+    inactive_grid: Grid,
+    alternative_buffer: Option<AlternativeBuffer>,
+    cursor_position: Position,
+    saved_cursor: SavedCursor,
+    auto_wrap: bool,
+    pending_wrap: bool,
+    tab_stops: Vec<bool>,
+    charsets: Charsets,
+    scroll_region: Range<u32>,
+    style: TerminalStyle,
+    cursor_visible: bool,
+    cursor_style: Option<CursorStyle>,
+    application_cursor: bool,
+    application_keypad: bool,
+    keyboard_flags: Vec<u8>,
+    inactive_keyboard_flags: Vec<u8>,
+    mouse_mode: MouseMode,
+    mouse_encoding: MouseEncoding,
+    bracketed_paste: bool,
+    base_palette: Palette,
+    palette: Palette,
+    palette_changed: bool,
+    replies: Vec<CompactString>,
+    terminal_name: &'static str,
+}
+
+impl TerminalState {
+    pub fn new(size: Position, scrollback_limit: usize, terminal_name: &'static str) -> Self {
+        Self {
+            grid: Grid::new(size, scrollback_limit),
+            inactive_grid: Grid::new(size, 0),
+            alternative_buffer: None,
+            cursor_position: Position::ZERO,
+            saved_cursor: SavedCursor::default(),
+            auto_wrap: true,
+            pending_wrap: false,
+            tab_stops: default_tab_stops(size.x),
+            charsets: Charsets::default(),
+            scroll_region: 0..size.y,
+            style: TerminalStyle::default(),
+            cursor_visible: true,
+            cursor_style: None,
+            application_cursor: false,
+            application_keypad: false,
+            keyboard_flags: Vec::new(),
+            inactive_keyboard_flags: Vec::new(),
+            mouse_mode: MouseMode::None,
+            mouse_encoding: MouseEncoding::Default,
+            bracketed_paste: false,
+            base_palette: Palette::default(),
+            palette: Palette::default(),
+            palette_changed: false,
+            replies: Vec::new(),
+            terminal_name,
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn scroll_display(&mut self, delta: isize) {
+        self.grid.scroll_display(delta);
+    }
+
+    pub fn reset_display(&mut self) {
+        self.grid.reset_display();
+    }
+
+    pub fn set_scrollback_limit(&mut self, scrollback_limit: usize) {
+        if self.alternative_buffer.is_some() {
+            self.inactive_grid.set_scrollback_limit(scrollback_limit);
+        } else {
+            self.grid.set_scrollback_limit(scrollback_limit);
+        }
+    }
+
+    pub fn alternative_buffer(&self) -> bool {
+        self.alternative_buffer.is_some()
+    }
+
+    fn swap_buffers(&mut self, buffer: Option<AlternativeBuffer>) {
+        mem::swap(&mut self.grid, &mut self.inactive_grid);
+        mem::swap(&mut self.keyboard_flags, &mut self.inactive_keyboard_flags);
+        self.alternative_buffer = buffer;
+    }
+
+    pub fn enable_alternative_buffer(&mut self, buffer: AlternativeBuffer) {
+        if self.alternative_buffer.is_some() {
+            return;
+        }
+
+        if buffer == AlternativeBuffer::SaveCursor {
+            self.save_cursor();
+        }
+
+        self.swap_buffers(Some(buffer));
+
+        if buffer == AlternativeBuffer::SaveCursor {
+            self.grid.clear(self.blank());
+        }
+    }
+
+    pub fn disable_alternative_buffer(&mut self, buffer: AlternativeBuffer) {
+        if self.alternative_buffer.is_none() {
+            return;
+        }
+
+        if buffer == AlternativeBuffer::ClearOnExit {
+            self.grid.clear(self.blank());
+        }
+
+        self.swap_buffers(None);
+
+        if buffer == AlternativeBuffer::SaveCursor {
+            self.restore_cursor();
+        }
+    }
+
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            position: self.cursor_position,
+            pending_wrap: self.pending_wrap,
+            style: self.style,
+            charsets: self.charsets,
+        };
+    }
+
+    pub fn restore_cursor(&mut self) {
+        let SavedCursor {
+            position,
+            pending_wrap,
+            style,
+            charsets,
+        } = self.saved_cursor;
+
+        self.goto(position);
+        self.pending_wrap = pending_wrap && self.auto_wrap;
+        self.style = style;
+        self.charsets = charsets;
+    }
+
+    pub fn cursor_position(&self) -> Position {
+        self.cursor_position
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// The cursor style requested with DECSCUSR, if any.
+    pub fn cursor_style(&self) -> Option<CursorStyle> {
+        self.cursor_style
+    }
+
+    pub fn application_cursor(&self) -> bool {
+        self.application_cursor
+    }
+
+    pub fn application_keypad(&self) -> bool {
+        self.application_keypad
+    }
+
+    pub fn mouse_mode(&self) -> MouseMode {
+        self.mouse_mode
+    }
+
+    pub fn mouse_encoding(&self) -> MouseEncoding {
+        self.mouse_encoding
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    pub fn keyboard_flags(&self) -> u8 {
+        self.keyboard_flags.last().copied().unwrap_or(0)
+    }
+
+    pub fn push_keyboard_flags(&mut self, flags: u8) {
+        if self.keyboard_flags.len() >= KEYBOARD_FLAGS_DEPTH {
+            self.keyboard_flags.remove(0);
+        }
+
+        self.keyboard_flags.push(flags);
+    }
+
+    pub fn pop_keyboard_flags(&mut self, count: u32) {
+        let len = self.keyboard_flags.len().saturating_sub(count as usize);
+
+        self.keyboard_flags.truncate(len);
+    }
+
+    pub fn set_keyboard_flags(&mut self, flags: u8, mode: u8) {
+        if self.keyboard_flags.is_empty() {
+            self.keyboard_flags.push(0);
+        }
+
+        let Some(current) = self.keyboard_flags.last_mut() else {
+            return;
+        };
+
+        match mode {
+            1 => *current = flags,
+            2 => *current |= flags,
+            3 => *current &= !flags,
+            _ => {}
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_base_palette(&mut self, palette: Palette) {
+        self.palette = palette.clone();
+        self.base_palette = palette;
+        self.palette_changed = true;
+    }
+
+    pub fn take_palette_changed(&mut self) -> bool {
+        mem::take(&mut self.palette_changed)
+    }
+
+    fn color_slot(palette: &mut Palette, slot: ColorSlot) -> &mut Rgb {
+        match slot {
+            ColorSlot::Index(index) => &mut palette.colors[index as usize],
+            ColorSlot::Foreground => &mut palette.foreground,
+            ColorSlot::Background => &mut palette.background,
+            ColorSlot::Cursor => &mut palette.cursor,
+            ColorSlot::SelectionBackground => &mut palette.selection_background,
+            ColorSlot::SelectionForeground => &mut palette.selection_foreground,
+        }
+    }
+
+    pub fn set_color(&mut self, slot: ColorSlot, [r, g, b]: [u8; 3]) {
+        *Self::color_slot(&mut self.palette, slot) = [r, g, b];
+        self.palette_changed = true;
+    }
+
+    pub fn reset_color(&mut self, slot: ColorSlot) {
+        *Self::color_slot(&mut self.palette, slot) =
+            *Self::color_slot(&mut self.base_palette, slot);
+        self.palette_changed = true;
+    }
+
+    pub fn query_color(&mut self, slot: ColorSlot, bell: bool) {
+        let [r, g, b] =
+            Self::color_slot(&mut self.palette, slot).map(|channel| u16::from(channel) * 0x0101);
+
+        let code = slot.code();
+        let index = match slot {
+            ColorSlot::Index(index) => format!("{index};"),
+            _ => String::new(),
+        };
+
+        let terminator = if bell { "\x07" } else { "\x1b\\" };
+
+        self.replies
+            .push(format!("\x1b]{code};{index}rgb:{r:04x}/{g:04x}/{b:04x}{terminator}").into());
+    }
+
+    pub fn take_replies(&mut self) -> Vec<CompactString> {
+        mem::take(&mut self.replies)
+    }
+
+    pub fn resize(&mut self, size: Position) {
+        if size == self.grid.size() {
+            return;
+        }
+
+        self.cursor_position = self.grid.resize(size, self.cursor_position);
+        self.pending_wrap = false;
+        self.tab_stops = default_tab_stops(size.x);
+
+        // Behind DECSET 1049 the main screen reflows around the cursor it'll return to.
+        if self.alternative_buffer == Some(AlternativeBuffer::SaveCursor) {
+            self.saved_cursor.position =
+                self.inactive_grid.resize(size, self.saved_cursor.position);
+        } else {
+            self.inactive_grid.resize(size, Position::ZERO);
+            self.saved_cursor.position = self.saved_cursor.position.min(self.max_position());
+        }
+
+        self.scroll_region = 0..size.y;
+    }
+
+    fn max_position(&self) -> Position {
+        let size = self.grid.size();
+
+        Position::new(size.x.saturating_sub(1), size.y.saturating_sub(1))
+    }
+
+    pub fn move_up(&mut self, rows: u32) {
+        debug_assert!(rows >= 1);
+
+        let top = if self.cursor_position.y >= self.scroll_region.start {
+            self.scroll_region.start
+        } else {
+            0
+        };
+
+        self.cursor_position.y = self.cursor_position.y.saturating_sub(rows).max(top);
+        self.pending_wrap = false;
+    }
+
+    pub fn move_down(&mut self, rows: u32) {
+        debug_assert!(rows >= 1);
+
+        let bottom = if self.cursor_position.y < self.scroll_region.end {
+            self.scroll_region.end - 1
+        } else {
+            self.max_position().y
+        };
+
+        self.cursor_position.y = self.cursor_position.y.saturating_add(rows).min(bottom);
+        self.pending_wrap = false;
+    }
+
+    pub fn move_left(&mut self, columns: u32) {
+        debug_assert!(columns >= 1);
+
+        self.cursor_position.x = self.cursor_position.x.saturating_sub(columns);
+        self.pending_wrap = false;
+    }
+
+    pub fn move_right(&mut self, columns: u32) {
+        debug_assert!(columns >= 1);
+
+        self.cursor_position.x = self
+            .cursor_position
+            .x
+            .saturating_add(columns)
+            .min(self.max_position().x);
+        self.pending_wrap = false;
+    }
+
+    pub fn horizontal_tab(&mut self, count: u32) {
+        let (x, y) = self.cursor_position.into();
+        let last = self.max_position().x;
+        let mut target = x;
+
+        for _ in 0..count {
+            target = (target + 1..last)
+                .find(|x| self.tab_stops[*x as usize])
+                .unwrap_or(last);
+        }
+
+        if target == x {
+            return;
+        }
+
+        if let Some(row) = self.grid.row_mut(y) {
+            let cells = &mut row.cells[x as usize..target as usize];
+
+            if cells.iter().all(Cell::is_blank) {
+                cells[0].tab = target - x;
+            }
+        }
+
+        self.goto_x(target);
+    }
+
+    pub fn backward_tab(&mut self, count: u32) {
+        let mut target = self.cursor_position.x;
+
+        for _ in 0..count {
+            target = (0..target)
+                .rev()
+                .find(|x| self.tab_stops[*x as usize])
+                .unwrap_or(0);
+        }
+
+        self.goto_x(target);
+    }
+
+    pub fn goto(&mut self, position: Position) {
+        self.cursor_position = position.min(self.max_position());
+        self.pending_wrap = false;
+    }
+
+    pub fn goto_x(&mut self, x: u32) {
+        self.cursor_position.x = x.min(self.max_position().x);
+        self.pending_wrap = false;
+    }
+
+    pub fn goto_y(&mut self, y: u32) {
+        self.cursor_position.y = y.min(self.max_position().y);
+        self.pending_wrap = false;
+    }
+
+    pub fn line_up(&mut self, rows: u32) {
+        self.move_up(rows);
+        self.goto_x(0);
+    }
+
+    pub fn line_down(&mut self, rows: u32) {
+        self.move_down(rows);
+        self.goto_x(0);
+    }
+
+    pub fn index(&mut self) {
+        if self.cursor_position.y + 1 == self.scroll_region.end {
+            let blank = self.blank();
+
+            self.grid.scroll_up(self.scroll_region.clone(), 1, blank);
+        } else if self.cursor_position.y < self.max_position().y {
+            self.cursor_position.y += 1;
+        }
+    }
+
+    pub fn reverse_index(&mut self) {
+        if self.cursor_position.y == self.scroll_region.start {
+            let blank = self.blank();
+
+            self.grid.insert_rows(self.scroll_region.clone(), 1, blank);
+        } else if self.cursor_position.y > 0 {
+            self.cursor_position.y -= 1;
+        }
+    }
+
+    pub fn next_line(&mut self) {
+        self.index();
+        self.goto_x(0);
+    }
+
+    pub fn set_scroll_region(&mut self, top: u32, bottom: Option<u32>) {
+        let rows = self.grid.size().y;
+        let bottom = bottom.unwrap_or(rows).min(rows);
+
+        if top + 1 >= bottom {
+            return;
+        }
+
+        self.scroll_region = top..bottom;
+        self.goto(Position::ZERO);
+    }
+
+    pub fn insert_lines(&mut self, count: u32) {
+        let y = self.cursor_position.y;
+
+        if !self.scroll_region.contains(&y) {
+            return;
+        }
+
+        let blank = self.blank();
+
+        self.grid
+            .insert_rows(y..self.scroll_region.end, count, blank);
+        self.goto_x(0);
+    }
+
+    pub fn delete_lines(&mut self, count: u32) {
+        let y = self.cursor_position.y;
+
+        if !self.scroll_region.contains(&y) {
+            return;
+        }
+
+        let blank = self.blank();
+
+        self.grid
+            .delete_rows(y..self.scroll_region.end, count, blank);
+        self.goto_x(0);
+    }
+
+    pub fn insert_characters(&mut self, count: u32) {
+        let blank = self.blank();
+        let (x, y) = self.cursor_position.min(self.max_position()).into();
+
+        let columns = self.grid.size().x;
+
+        self.split_wide_before(x, y);
+
+        // Cells shifted off the end may take the second half of a wide character with them.
+        if x.saturating_add(count) < columns {
+            self.split_wide_before(columns - count, y);
+        }
+
+        if let Some(row) = self.grid.row_mut(y) {
+            row.insert_cells(x, count, blank);
+        }
+    }
+
+    pub fn delete_characters(&mut self, count: u32) {
+        let blank = self.blank();
+        let (x, y) = self.cursor_position.min(self.max_position()).into();
+
+        self.split_wide_before(x, y);
+        self.split_wide_before(x.saturating_add(count), y);
+
+        if let Some(row) = self.grid.row_mut(y) {
+            row.delete_cells(x, count, blank);
+        }
+    }
+
+    pub fn erase_characters(&mut self, count: u32) {
+        let (x, y) = self.cursor_position.min(self.max_position()).into();
+
+        self.split_wide_before(x, y);
+        self.split_wide_before(x.saturating_add(count), y);
+        self.clear_row(y, x..x.saturating_add(count));
+    }
+
+    pub fn screen_alignment_test(&mut self) {
+        let blank = Cell::new('E', TerminalStyle::default());
+
+        self.grid.clear(blank);
+        self.scroll_region = 0..self.grid.size().y;
+        self.goto(Position::ZERO);
+    }
+
+    pub fn echo(&mut self, character: char) {
+        let character = self.charsets.map(character);
+        let width = character.width().unwrap_or(1) as u32;
+
+        // ASCII never continues a grapheme cluster, so skip segmenting the common case.
+        if !character.is_ascii() && self.combine(character) {
+            return;
+        }
+
+        let columns = self.grid.size().x;
+
+        if width == 0 || width > columns {
+            return;
+        }
+
+        if self.pending_wrap {
+            self.wrap();
+        }
+
+        if self.cursor_position.x + width > columns {
+            if self.auto_wrap {
+                self.wrap();
+            } else {
+                self.cursor_position.x = columns - width;
+            }
+        }
+
+        let (x, y) = self.cursor_position.into();
+
+        self.split_tab(x, y);
+
+        for x in x..x + width {
+            self.split_wide(Position::new(x, y));
+        }
+
+        if let Some(cell) = self.grid.cell_mut(self.cursor_position) {
+            *cell = Cell::new(character, self.style);
+            cell.wide = width == 2;
+        }
+
+        if width == 2 {
+            let spacer = Cell {
+                wide_spacer: true,
+                ..self.blank()
+            };
+
+            if let Some(cell) = self.grid.cell_mut(Position::new(x + 1, y)) {
+                *cell = spacer;
+            }
+        }
+
+        // Like xterm, the cursor stays on the last column until the next character wraps it.
+        if x + width < columns {
+            self.cursor_position.x += width;
+        } else {
+            self.cursor_position.x = columns - 1;
+            self.pending_wrap = self.auto_wrap;
+        }
+    }
+
+    fn wrap(&mut self) {
+        if let Some(row) = self.grid.row_mut(self.cursor_position.y) {
+            row.wrapped = true;
+        }
+
+        self.cursor_position.x = 0;
+        self.pending_wrap = false;
+        self.index();
+    }
+
+    /// Appends `character` to the previous cell if it continues that cell's grapheme cluster.
+    fn combine(&mut self, character: char) -> bool {
+        let (x, y) = self.cursor_position.into();
+
+        let Some(row) = self.grid.row_mut(y) else {
+            return false;
+        };
+
+        let x = if self.pending_wrap {
+            Some(x)
+        } else {
+            x.checked_sub(1)
+        };
+
+        let Some(mut x) = x else {
+            return false;
+        };
+
+        if x > 0 && row.cells[x as usize].wide_spacer {
+            x -= 1;
+        }
+
+        let Some(cell) = row.cells.get_mut(x as usize) else {
+            return false;
+        };
+
+        let mut text = cell.text();
+
+        text.push(character);
+
+        if text.graphemes(true).nth(1).is_some() {
+            return false;
+        }
+
+        if cell.combining.len() < COMBINING_LIMIT {
+            cell.combining.push(character);
+        }
+
+        true
+    }
+
+    /// Forgets a tab that skipped over column `x`, which is about to be written to.
+    fn split_tab(&mut self, x: u32, y: u32) {
+        let Some(row) = self.grid.row_mut(y) else {
+            return;
+        };
+
+        // Cells a tab skipped stay blank until written, so only blanks need searching.
+        let start = row.cells[..=x as usize]
+            .iter()
+            .rposition(|cell| cell.tab > 0 || !cell.is_blank());
+
+        if let Some(start) = start {
+            let cell = &mut row.cells[start];
+
+            if start as u32 + cell.tab > x {
+                cell.tab = 0;
+            }
+        }
+    }
+
+    /// Blanks the other half of a wide character about to be overwritten at `position`.
+    fn split_wide(&mut self, position: Position) {
+        let Some(cell) = self.grid.cell_mut(position) else {
+            return;
+        };
+
+        let other = if cell.wide_spacer && position.x > 0 {
+            Position::new(position.x - 1, position.y)
+        } else if cell.wide {
+            Position::new(position.x + 1, position.y)
+        } else {
+            return;
+        };
+
+        let blank = self.blank();
+
+        if let Some(cell) = self.grid.cell_mut(other) {
+            *cell = blank;
+        }
+    }
+
+    /// Blanks a wide character straddling columns `x - 1` and `x`, before cells are shifted
+    /// or erased from `x` on.
+    fn split_wide_before(&mut self, x: u32, y: u32) {
+        let position = Position::new(x, y);
+
+        if !self
+            .grid
+            .cell_mut(position)
+            .is_some_and(|cell| cell.wide_spacer)
+        {
+            return;
+        }
+
+        self.split_wide(position);
+
+        let blank = self.blank();
+
+        if let Some(cell) = self.grid.cell_mut(position) {
+            *cell = blank;
+        }
+    }
+
+    /// The state of a DEC private mode, or `None` if it isn't implemented.
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        let enabled = match mode {
+            1 => self.application_cursor,
+            7 => self.auto_wrap,
+            9 => self.mouse_mode == MouseMode::Press,
+            25 => self.cursor_visible,
+            47 => self.alternative_buffer == Some(AlternativeBuffer::Plain),
+            1000 => self.mouse_mode == MouseMode::Click,
+            1002 => self.mouse_mode == MouseMode::Drag,
+            1003 => self.mouse_mode == MouseMode::Motion,
+            1005 => self.mouse_encoding == MouseEncoding::Utf8,
+            1006 => self.mouse_encoding == MouseEncoding::Sgr,
+            1015 => self.mouse_encoding == MouseEncoding::Urxvt,
+            1047 => self.alternative_buffer == Some(AlternativeBuffer::ClearOnExit),
+            1049 => self.alternative_buffer == Some(AlternativeBuffer::SaveCursor),
+            2004 => self.bracketed_paste,
+            _ => return None,
+        };
+
+        Some(enabled)
+    }
+
+    fn report_setting(&mut self, setting: &str) {
+        let value = match setting {
+            "m" => format!("{}m", sgr(&self.style)),
+            "r" => format!(
+                "{};{}r",
+                self.scroll_region.start + 1,
+                self.scroll_region.end
+            ),
+            " q" => {
+                let style = match self.cursor_style {
+                    None => 0,
+                    Some(CursorStyle { shape, blink }) => {
+                        let style = match shape {
+                            CursorShape::Block => 1,
+                            CursorShape::Underline => 3,
+                            CursorShape::Bar => 5,
+                        };
+
+                        style + u8::from(!blink)
+                    }
+                };
+
+                format!("{style} q")
+            }
+            _ => {
+                self.replies.push("\x1bP0$r\x1b\\".into());
+
+                return;
+            }
+        };
+
+        self.replies.push(format!("\x1bP1$r{value}\x1b\\").into());
+    }
+
+    pub fn reset(&mut self) {
+        mem::take(&mut self.style);
+    }
+
+    /// Puts every mode back to its default for a respawned command, keeping the main screen.
+    pub fn reset_modes(&mut self) {
+        if let Some(buffer) = self.alternative_buffer {
+            self.disable_alternative_buffer(buffer);
+        }
+
+        let fresh = Self::new(self.grid.size(), 0, self.terminal_name);
+        let Self {
+            grid,
+            cursor_position,
+            base_palette,
+            ..
+        } = mem::replace(self, fresh);
+
+        self.grid = grid;
+        self.cursor_position = cursor_position;
+        self.palette = base_palette.clone();
+        self.base_palette = base_palette;
+        self.palette_changed = true;
+    }
+
+    fn blank(&self) -> Cell {
+        Cell::blank(self.style)
+    }
+
+    fn clear_row(&mut self, y: u32, range: Range<u32>) {
+        let blank = self.blank();
+
+        if let Some(row) = self.grid.row_mut(y) {
+            row.clear(range, blank);
+        }
+    }
+
+    pub fn clear_left(&mut self) {
+        let (x, y) = self.cursor_position.into();
+
+        self.clear_row(y, 0..x + 1);
+    }
+
+    pub fn clear_right(&mut self) {
+        let (x, y) = self.cursor_position.into();
+
+        self.clear_row(y, x..self.grid.size().x);
+    }
+
+    pub fn clear_line(&mut self) {
+        let y = self.cursor_position.y;
+
+        self.clear_row(y, 0..self.grid.size().x);
+    }
+
+    pub fn clear_up(&mut self) {
+        self.clear_left();
+
+        for y in 0..self.cursor_position.y {
+            self.clear_row(y, 0..self.grid.size().x);
+        }
+    }
+
+    pub fn clear_down(&mut self) {
+        self.clear_right();
+
+        for y in self.cursor_position.y + 1..self.grid.size().y {
+            self.clear_row(y, 0..self.grid.size().x);
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        let blank = self.blank();
+
+        self.grid.clear(blank);
+    }
+
+    pub fn handle(&mut self, event: VteEvent) {
+        match event {
+            VteEvent::Echo(character) => self.echo(character),
+            VteEvent::Backspace => self.move_left(1),
+            VteEvent::HorizontalTab(count) => self.horizontal_tab(count),
+            VteEvent::BackwardTab(count) => self.backward_tab(count),
+            VteEvent::SetTabStop => self.tab_stops[self.cursor_position.x as usize] = true,
+            VteEvent::ClearTabStop => self.tab_stops[self.cursor_position.x as usize] = false,
+            VteEvent::ClearAllTabStops => self.tab_stops.fill(false),
+            VteEvent::LineFeed | VteEvent::Index => self.index(),
+            VteEvent::ReverseIndex => self.reverse_index(),
+            VteEvent::NextLine => self.next_line(),
+            VteEvent::SetScrollRegion(top, bottom) => self.set_scroll_region(top, bottom),
+            VteEvent::ScreenAlignmentTest => self.screen_alignment_test(),
+
+            VteEvent::InsertLines(count) => self.insert_lines(count),
+            VteEvent::DeleteLines(count) => self.delete_lines(count),
+            VteEvent::InsertCharacters(count) => self.insert_characters(count),
+            VteEvent::DeleteCharacters(count) => self.delete_characters(count),
+            VteEvent::EraseCharacters(count) => self.erase_characters(count),
+
+            VteEvent::Goto(position) => self.goto(position),
+            VteEvent::GotoX(x) => self.goto_x(x),
+            VteEvent::GotoY(y) => self.goto_y(y),
+
+            VteEvent::EnableApplicationCursor => self.application_cursor = true,
+            VteEvent::DisableApplicationCursor => self.application_cursor = false,
+            VteEvent::EnableApplicationKeypad => self.application_keypad = true,
+            VteEvent::DisableApplicationKeypad => self.application_keypad = false,
+
+            VteEvent::EnableAlternativeBuffer(buffer) => self.enable_alternative_buffer(buffer),
+            VteEvent::DisableAlternativeBuffer(buffer) => self.disable_alternative_buffer(buffer),
+
+            VteEvent::ShowCursor => self.cursor_visible = true,
+            VteEvent::HideCursor => self.cursor_visible = false,
+            VteEvent::SetCursorStyle(style) => self.cursor_style = style,
+
+            VteEvent::SetMouseMode(mode) => self.mouse_mode = mode,
+            VteEvent::SetMouseEncoding(encoding) => self.mouse_encoding = encoding,
+
+            VteEvent::EnableAutoWrap => self.auto_wrap = true,
+            VteEvent::DisableAutoWrap => {
+                self.auto_wrap = false;
+                self.pending_wrap = false;
+            }
+
+            VteEvent::EnableBracketedPaste => self.bracketed_paste = true,
+            VteEvent::DisableBracketedPaste => self.bracketed_paste = false,
+
+            VteEvent::PushKeyboardFlags(flags) => self.push_keyboard_flags(flags),
+            VteEvent::PopKeyboardFlags(count) => self.pop_keyboard_flags(count),
+            VteEvent::SetKeyboardFlags { flags, mode } => self.set_keyboard_flags(flags, mode),
+            VteEvent::QueryKeyboardFlags => {
+                let flags = self.keyboard_flags();
+
+                self.replies.push(format!("\x1b[?{flags}u").into());
+            }
+
+            VteEvent::LineUp(rows) => self.line_up(rows),
+            VteEvent::LineDown(rows) => self.line_down(rows),
+
+            VteEvent::MoveUp(rows) => self.move_up(rows),
+            VteEvent::MoveDown(rows) => self.move_down(rows),
+            VteEvent::MoveLeft(columns) => self.move_left(columns),
+            VteEvent::MoveRight(columns) => self.move_right(columns),
+
+            VteEvent::SaveCursorPosition => self.save_cursor(),
+            VteEvent::RestoreCursorPosition => self.restore_cursor(),
+
+            VteEvent::DesignateCharset(index, charset) => self.charsets.designate(index, charset),
+            VteEvent::InvokeCharset(index) => self.charsets.invoke_left(index),
+            VteEvent::InvokeCharsetRight(index) => self.charsets.invoke_right(index),
+            VteEvent::SingleShift(index) => self.charsets.single_shift(index),
+
+            VteEvent::Reset => self.reset(),
+            VteEvent::Bold => self.style.bold = true,
+            VteEvent::Dim => self.style.dim = true,
+            VteEvent::Italic => self.style.italic = true,
+            VteEvent::Underline(underline) => self.style.underline = underline,
+            VteEvent::Blink => self.style.blink = true,
+            VteEvent::Inverse => self.style.inverse = true,
+            VteEvent::Hidden => self.style.hidden = true,
+            VteEvent::Strikethrough => self.style.strikethrough = true,
+            VteEvent::Overline => self.style.overline = true,
+            VteEvent::ResetIntensity => {
+                self.style.bold = false;
+                self.style.dim = false;
+            }
+            VteEvent::ResetItalic => self.style.italic = false,
+            VteEvent::ResetUnderline => self.style.underline = UnderlineStyle::None,
+            VteEvent::ResetBlink => self.style.blink = false,
+            VteEvent::ResetInverse => self.style.inverse = false,
+            VteEvent::ResetHidden => self.style.hidden = false,
+            VteEvent::ResetStrikethrough => self.style.strikethrough = false,
+            VteEvent::ResetOverline => self.style.overline = false,
+            VteEvent::Foreground(color) => self.style.foreground = Some(color),
+            VteEvent::ResetForeground => self.style.foreground = None,
+            VteEvent::Background(color) => self.style.background = Some(color),
+            VteEvent::ResetBackground => self.style.background = None,
+            VteEvent::UnderlineColor(color) => self.style.underline_color = Some(color),
+            VteEvent::ResetUnderlineColor => self.style.underline_color = None,
+
+            VteEvent::Image(_image) => {
+                let (x, y) = self.cursor_position.into();
+
+                for j in y..y + 10 {
+                    self.clear_row(j, x..x + 10);
+                }
+
+                self.move_right(10);
+            }
+
+            VteEvent::SetColor(slot, color) => self.set_color(slot, color),
+            VteEvent::QueryColor { slot, bell } => self.query_color(slot, bell),
+            VteEvent::ResetColor(slot) => self.reset_color(slot),
+            VteEvent::ResetPalette => {
+                self.palette.colors = self.base_palette.colors;
+                self.palette_changed = true;
+            }
+
+            VteEvent::ReportCursorPosition => {
+                let (x, y) = self.cursor_position.min(self.max_position()).into();
+
+                self.replies
+                    .push(format!("\x1b[{};{}R", y + 1, x + 1).into());
+            }
+            // VT220 with ANSI colour.
+            VteEvent::ReportDeviceAttributes => self.replies.push("\x1b[?62;22c".into()),
+            VteEvent::ReportSecondaryDeviceAttributes => {
+                self.replies
+                    .push(format!("\x1b[>1;{};0c", version()).into());
+            }
+            VteEvent::ReportTerminalId => self.replies.push("\x1bP!|00000000\x1b\\".into()),
+            VteEvent::ReportVersion => {
+                let name = env!("CARGO_PKG_NAME");
+                let version = env!("CARGO_PKG_VERSION");
+
+                self.replies
+                    .push(format!("\x1bP>|{name} {version}\x1b\\").into());
+            }
+            // No ANSI modes are implemented.
+            VteEvent::ReportMode(mode) => self.replies.push(format!("\x1b[{mode};0$y").into()),
+            VteEvent::ReportPrivateMode(mode) => {
+                let state = match self.private_mode(mode) {
+                    Some(true) => 1,
+                    Some(false) => 2,
+                    None => 0,
+                };
+
+                self.replies.push(format!("\x1b[?{mode};{state}$y").into());
+            }
+            VteEvent::ReportSetting(setting) => self.report_setting(&setting),
+            VteEvent::ReportCapability(name) => {
+                let value = match &*name {
+                    "TN" | "name" => Some(Some(self.terminal_name.to_string())),
+                    _ => terminfo::capability(&name),
+                };
+
+                let reply = match value {
+                    Some(Some(value)) => format!("\x1bP1+r{}={}\x1b\\", hex(&name), hex(&value)),
+                    Some(None) => format!("\x1bP1+r{}\x1b\\", hex(&name)),
+                    None => format!("\x1bP0+r{}\x1b\\", hex(&name)),
+                };
+
+                self.replies.push(reply.into());
+            }
+
+            VteEvent::ClearLeft => self.clear_left(),
+            VteEvent::ClearRight => self.clear_right(),
+            VteEvent::ClearLine => self.clear_line(),
+            VteEvent::ClearUp => self.clear_up(),
+            VteEvent::ClearDown => self.clear_down(),
+            VteEvent::ClearAll => self.clear_all(),
+            VteEvent::ClearEverything => self.grid.clear_scrollback(),
+
+            _ => {}
+        }
+    }
+}
+
+fn default_colors() -> [Rgb; 256] {
+    std::array::from_fn(|index| match index {
+        0..16 => {
+            let [_, r, g, b] = ANSI[index].to_be_bytes();
+
+            [r, g, b]
+        }
+        16..232 => {
+            let index = index as u8 - 16;
+
+            [index / 36, (index / 6) % 6, index % 6].map(|level| {
+                if level == 0 {
+                    0
+                } else {
+                    (level * 40) + 55
+                }
+            })
+        }
+        _ => [((index as u8 - 232) * 10) + 8; 3],
+    })
+}
+
+fn default_tab_stops(columns: u32) -> Vec<bool> {
+    (0..columns).map(|x| x.is_multiple_of(8)).collect()
+}
+
+fn hex(string: &str) -> String {
+    string.bytes().map(|byte| format!("{byte:02X}")).collect()
+}
+
+/// The crate version packed like xterm's patch level, for secondary device attributes.
+fn version() -> u32 {
+    let part = |part: &str| part.parse::<u32>().unwrap_or(0);
+
+    part(env!("CARGO_PKG_VERSION_MAJOR")) * 10000
+        + part(env!("CARGO_PKG_VERSION_MINOR")) * 100
+        + part(env!("CARGO_PKG_VERSION_PATCH"))
+}
+
+/// The SGR parameters that select `style` from a reset state.
+fn sgr(style: &TerminalStyle) -> String {
+    let mut parameters = vec![String::from("0")];
+
+    let flags = [
+        (style.bold, "1"),
+        (style.dim, "2"),
+        (style.italic, "3"),
+        (style.blink, "5"),
+        (style.inverse, "7"),
+        (style.hidden, "8"),
+        (style.strikethrough, "9"),
+        (style.overline, "53"),
+    ];
+
+    let underline = match style.underline {
+        UnderlineStyle::None => None,
+        UnderlineStyle::Single => Some("4"),
+        UnderlineStyle::Double => Some("4:2"),
+        UnderlineStyle::Curly => Some("4:3"),
+        UnderlineStyle::Dotted => Some("4:4"),
+        UnderlineStyle::Dashed => Some("4:5"),
+    };
+
+    parameters.extend(underline.map(String::from));
+    parameters.extend(
+        flags
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, parameter)| String::from(*parameter)),
+    );
+
+    let colors = [
+        (style.foreground, Some(30), 38),
+        (style.background, Some(40), 48),
+        (style.underline_color, None, 58),
+    ];
+
+    for (color, base, extended) in colors {
+        let parameter = match (color, base) {
+            (None, _) => continue,
+            (Some(AnsiColor::Standard(StandardColor { color, intensity })), Some(base)) => {
+                match intensity {
+                    Intensity::Bright => format!("{}", base + 60 + color as u16),
+                    Intensity::Normal | Intensity::Dim => format!("{}", base + color as u16),
+                }
+            }
+            (Some(AnsiColor::Standard(StandardColor { color, intensity })), None) => {
+                let offset = if intensity == Intensity::Bright { 8 } else { 0 };
+
+                format!("{extended};5;{}", color as u16 + offset)
+            }
+            (Some(AnsiColor::Index(index)), _) => format!("{extended};5;{index}"),
+            (Some(AnsiColor::Rgb(r, g, b)), _) => format!("{extended};2;{r};{g};{b}"),
+        };
+
+        parameters.push(parameter);
+    }
+
+    parameters.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vte::{Vte, VteHandler};
+
+    impl VteHandler for &mut TerminalState {
+        fn vte_event(&mut self, event: VteEvent) {
+            self.handle(event);
+        }
+    }
+
+    fn terminal(columns: u32, rows: u32) -> TerminalState {
+        TerminalState::new(Position::new(columns, rows), 100, terminfo::NAME)
+    }
+
+    fn run(state: &mut TerminalState, bytes: impl AsRef<[u8]>) {
+        Vte::new(state).process(bytes.as_ref());
+    }
+
+    fn screen(state: &TerminalState) -> Vec<String> {
+        state
+            .grid()
+            .viewport()
+            .map(|row| {
+                let text = row
+                    .cells
+                    .iter()
+                    .filter(|cell| !cell.wide_spacer)
+                    .map(Cell::text)
+                    .collect::<String>();
+
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn print_and_move() {
+        let mut state = terminal(10, 3);
+
+        run(&mut state, "hello\r\nworld\x1b[1;3Hx");
+
+        assert_eq!(screen(&state), ["hexlo", "world", ""]);
+        assert_eq!(state.cursor_position(), Position::new(3, 0));
+
+        run(&mut state, "\x1b[2B\x1b[3D\x1b[A!");
+
+        assert_eq!(screen(&state), ["hexlo", "!orld", ""]);
+    }
+
+    #[test]
+    fn erase() {
+        let mut state = terminal(5, 3);
+
+        run(&mut state, "aaaaa\r\nbbbbb\r\nccccc\x1b[2;3H\x1b[K");
+
+        assert_eq!(screen(&state), ["aaaaa", "bb", "ccccc"]);
+
+        run(&mut state, "\x1b[1K\x1b[1;2H\x1b[2K");
+
+        assert_eq!(screen(&state), ["", "", "ccccc"]);
+
+        run(&mut state, "\x1b[3;4H\x1b[1J");
+
+        assert_eq!(screen(&state), ["", "", "    c"]);
+    }
+
+    #[test]
+    fn report_cursor_position() {
+        let mut state = terminal(10, 3);
+
+        run(&mut state, "ab\r\nc\x1b[6n");
+
+        assert_eq!(state.take_replies(), ["\x1b[2;2R"]);
+    }
+
+    #[test]
+    fn keep_style_per_cell() {
+        let mut state = terminal(10, 1);
+
+        run(&mut state, "a\x1b[1;31mb\x1b[0mc");
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+
+        assert!(!cells[0].style.bold);
+        assert!(cells[1].style.bold);
+        assert_eq!(
+            cells[1].style.foreground,
+            Some(AnsiColor::Standard(StandardColor {
+                color: crate::vte::NamedColor::Red,
+                intensity: Intensity::Normal,
+            }))
+        );
+        assert_eq!(cells[2].style, TerminalStyle::default());
+    }
+
+    #[test]
+    fn scroll_within_region() {
+        let mut state = terminal(4, 4);
+
+        run(&mut state, "a\r\nb\r\nc\r\nd\x1b[2;3r");
+
+        assert_eq!(state.cursor_position(), Position::ZERO);
+
+        run(&mut state, "\x1b[3;1H\nX");
+
+        assert_eq!(screen(&state), ["a", "c", "X", "d"]);
+        assert_eq!(state.grid().scrollback_len(), 0);
+
+        run(&mut state, "\x1b[2;1H\x1bMY");
+
+        assert_eq!(screen(&state), ["a", "Y", "c", "d"]);
+    }
+
+    #[test]
+    fn index_and_alignment_test() {
+        let mut state = terminal(4, 4);
+
+        run(&mut state, "\x1b#8");
+
+        assert_eq!(screen(&state), ["EEEE"; 4]);
+
+        run(&mut state, "\x1b[4;3H\x1bDZ\x1bEY");
+
+        assert_eq!(screen(&state), ["EEEE", "EEEE", "  Z", "Y"]);
+        assert_eq!(state.grid().scrollback_len(), 2);
+    }
+
+    #[test]
+    fn insert_and_delete_characters() {
+        let mut state = terminal(6, 1);
+
+        run(&mut state, "abcdef\x1b[1;3H\x1b[2@");
+
+        assert_eq!(screen(&state), ["ab  cd"]);
+
+        run(&mut state, "\x1b[3P");
+
+        assert_eq!(screen(&state), ["abd"]);
+
+        run(&mut state, "\x1b[1;1H\x1b[2X");
+
+        assert_eq!(screen(&state), ["  d"]);
+    }
+
+    #[test]
+    fn insert_and_delete_lines() {
+        let mut state = terminal(3, 3);
+
+        run(&mut state, "a\r\nb\r\nc\x1b[2;2H\x1b[L");
+
+        assert_eq!(screen(&state), ["a", "", "b"]);
+        assert_eq!(state.cursor_position(), Position::new(0, 1));
+
+        run(&mut state, "\x1b[1;1H\x1b[2M");
+
+        assert_eq!(screen(&state), ["b", "", ""]);
+        assert_eq!(state.grid().scrollback_len(), 0);
+    }
+
+    #[test]
+    fn select_graphic_rendition() {
+        let mut state = terminal(10, 1);
+
+        run(
+            &mut state,
+            "\x1b[1;31;4:3;38:2::1:2:3;48;5;200;9;7ma\x1b[22;24;27mb\x1b[38;2;9;8;7m\x1b[mc",
+        );
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+        let style = cells[0].style;
+
+        assert!(style.bold && style.inverse && style.strikethrough);
+        assert_eq!(style.underline, UnderlineStyle::Curly);
+        assert_eq!(style.foreground, Some(AnsiColor::Rgb(1, 2, 3)));
+        assert_eq!(style.background, Some(AnsiColor::Index(200)));
+
+        let style = cells[1].style;
+
+        assert!(!style.bold && !style.inverse && style.strikethrough);
+        assert_eq!(style.underline, UnderlineStyle::None);
+        assert_eq!(cells[2].style, TerminalStyle::default());
+    }
+
+    #[test]
+    fn reset_modes_for_respawn() {
+        let mut state = terminal(10, 4);
+
+        run(
+            &mut state,
+            "main\x1b[?1049h\x1b[?1000h\x1b[?2004h\x1b[>1u\x1b(0\x1b[2;3r\x1b[?1h",
+        );
+
+        state.reset_modes();
+
+        assert!(!state.alternative_buffer());
+        assert!(!state.bracketed_paste());
+        assert!(!state.application_cursor());
+        assert_eq!(state.mouse_mode(), MouseMode::None);
+        assert_eq!(state.keyboard_flags(), 0);
+        assert_eq!(state.cursor_position(), Position::new(4, 0));
+
+        run(&mut state, "\r\nq\r\n\r\n\r\n");
+
+        assert_eq!(screen(&state), ["q", "", "", ""]);
+    }
+
+    #[test]
+    fn alternate_screen() {
+        let mut state = terminal(5, 3);
+
+        run(&mut state, "main\r\nx\x1b[?1049h");
+
+        assert!(state.alternative_buffer());
+        assert_eq!(screen(&state), ["", "", ""]);
+
+        run(&mut state, "\x1b[Halt\r\n\r\n\r\n\r\n");
+
+        assert_eq!(state.grid().scrollback_len(), 0);
+
+        run(&mut state, "\x1b[?1049l");
+
+        assert_eq!(screen(&state), ["main", "x", ""]);
+        assert_eq!(state.cursor_position(), Position::new(1, 1));
+
+        run(&mut state, "\x1b[?47hab\x1b[?47l\x1b[?47h");
+
+        assert_eq!(screen(&state)[1], " ab");
+
+        run(&mut state, "\x1b[?1047l\x1b[?1047h");
+
+        assert_eq!(screen(&state), ["", "", ""]);
+    }
+
+    #[test]
+    fn resize_main_screen_behind_alternate_screen() {
+        let mut state = terminal(10, 4);
+
+        run(&mut state, "a\r\nb\r\nc\r\nd\x1b[?1049h");
+
+        state.resize(Position::new(5, 4));
+        state.resize(Position::new(10, 4));
+
+        run(&mut state, "\x1b[?1049l");
+
+        assert_eq!(screen(&state), ["a", "b", "c", "d"]);
+        assert_eq!(state.cursor_position(), Position::new(1, 3));
+
+        run(&mut state, "\x1b[?1049h");
+
+        state.resize(Position::new(10, 2));
+
+        run(&mut state, "\x1b[?1049lx");
+
+        assert_eq!(screen(&state), ["c", "dx"]);
+        assert_eq!(state.grid().scrollback_len(), 2);
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        let mut state = terminal(5, 3);
+
+        run(&mut state, "a中e\u{301}b");
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+
+        assert!(cells[1].wide && cells[2].wide_spacer);
+        assert_eq!(cells[3].text(), "e\u{301}");
+        assert_eq!(screen(&state)[0], "a中e\u{301}b");
+
+        run(&mut state, "中\x1b[2;2Hx");
+
+        let rows = state.grid().viewport().collect::<Vec<_>>();
+
+        assert!(rows[0].wrapped);
+        assert!(!rows[1].cells[0].wide && !rows[1].cells[1].wide_spacer);
+        assert_eq!(screen(&state)[1], " x");
+
+        run(&mut state, "\x1b[3;1H👨\u{200d}👩");
+
+        assert_eq!(screen(&state)[2], "👨\u{200d}👩");
+        assert_eq!(state.cursor_position(), Position::new(2, 2));
+    }
+
+    #[test]
+    fn edit_characters_split_wide_characters() {
+        let mut state = terminal(6, 1);
+
+        run(&mut state, "a中b\x1b[1;3H\x1b[@");
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+
+        assert!(cells.iter().all(|cell| !cell.wide && !cell.wide_spacer));
+        assert_eq!(screen(&state), ["a   b"]);
+
+        let mut state = terminal(6, 1);
+
+        run(&mut state, "a中b\x1b[1;1H\x1b[P");
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+
+        assert!(cells[0].wide && cells[1].wide_spacer);
+
+        run(&mut state, "\x1b[1;2H\x1b[X");
+
+        assert_eq!(screen(&state), ["  b"]);
+
+        let mut state = terminal(4, 1);
+
+        run(&mut state, "ab中\x1b[1;1H\x1b[@");
+
+        let cells = &state.grid().viewport().next().unwrap().cells;
+
+        assert!(cells.iter().all(|cell| !cell.wide && !cell.wide_spacer));
+        assert_eq!(screen(&state), [" ab"]);
+    }
+
+    #[test]
+    fn auto_wrap_mode() {
+        let mut state = terminal(5, 3);
+
+        run(&mut state, "abcde\x1b[6n");
+
+        assert_eq!(state.cursor_position(), Position::new(4, 0));
+        assert_eq!(state.take_replies(), ["\x1b[1;5R"]);
+
+        run(&mut state, "f");
+
+        assert_eq!(screen(&state), ["abcde", "f", ""]);
+        assert!(state.grid().viewport().next().unwrap().wrapped);
+
+        run(&mut state, "\x1b[?7l\x1b[3;1Hxyzuvw");
+
+        assert_eq!(screen(&state), ["abcde", "f", "xyzuw"]);
+        assert_eq!(state.cursor_position(), Position::new(4, 2));
+
+        run(&mut state, "\x1b[?7h\x1b[3;1Habcde\rQ");
+
+        assert_eq!(screen(&state), ["abcde", "f", "Qbcde"]);
+        assert_eq!(state.cursor_position(), Position::new(1, 2));
+    }
+
+    #[test]
+    fn report_device_attributes() {
+        let mut state = terminal(5, 3);
+
+        run(&mut state, "\x1b[c\x1b[>c\x1b[=c\x1b[>q");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b[?62;22c".to_string(),
+                format!("\x1b[>1;{};0c", version()),
+                "\x1bP!|00000000\x1b\\".to_string(),
+                format!(
+                    "\x1bP>|milkshake-terminal {}\x1b\\",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_modes() {
+        let mut state = terminal(5, 3);
+
+        run(
+            &mut state,
+            "\x1b[?2004h\x1b[?2004$p\x1b[?7$p\x1b[?1$p\x1b[?12345$p\x1b[4$p",
+        );
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b[?2004;1$y",
+                "\x1b[?7;1$y",
+                "\x1b[?1;2$y",
+                "\x1b[?12345;0$y",
+                "\x1b[4;0$y",
+            ]
+        );
+
+        run(&mut state, "\x1b[?1049h\x1b[?47$p\x1b[?1047$p\x1b[?1049$p");
+
+        assert_eq!(
+            state.take_replies(),
+            ["\x1b[?47;2$y", "\x1b[?1047;2$y", "\x1b[?1049;1$y"]
+        );
+
+        run(&mut state, "\x1b[?1049l\x1b[?47h\x1b[?47$p\x1b[?1049$p");
+
+        assert_eq!(state.take_replies(), ["\x1b[?47;1$y", "\x1b[?1049;2$y"]);
+    }
+
+    #[test]
+    fn report_settings() {
+        let mut state = terminal(5, 3);
+
+        run(
+            &mut state,
+            "\x1b[1;4:3;91;48;5;200;58;2;1;2;3m\x1bP$qm\x1b\\\x1b[2;3r\x1bP$qr\x1b\\",
+        );
+        run(&mut state, "\x1b[4 q\x1bP$q q\x1b\\\x1bP$qx\x1b\\");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1bP1$r0;4:3;1;91;48;5;200;58;2;1;2;3m\x1b\\",
+                "\x1bP1$r2;3r\x1b\\",
+                "\x1bP1$r4 q\x1b\\",
+                "\x1bP0$r\x1b\\",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_capabilities() {
+        let mut state = terminal(5, 3);
+
+        // "Co", "Tc", "zz" and a malformed name.
+        run(&mut state, "\x1bP+q436F;5463;7A7A;4\x1b\\\x1bP+q544E\x1b\\");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1bP1+r436F=323536\x1b\\",
+                "\x1bP1+r5463\x1b\\",
+                "\x1bP0+r7A7A\x1b\\",
+                "\x1bP1+r544E=6D696C6B7368616B65\x1b\\",
+            ]
+        );
+    }
+
+    fn copy_lines(state: &TerminalState, lines: usize) -> String {
+        use crate::selection::{Point, Selection, SelectionMode};
+
+        let start = Point {
+            line: state.grid().viewport_start(),
+            column: 0,
+        };
+        let mut selection = Selection::new(SelectionMode::Simple, start);
+
+        selection.update(Point {
+            line: start.line + lines - 1,
+            column: state.grid().size().x - 1,
+        });
+
+        selection.span(state.grid()).unwrap().text(state.grid())
+    }
+
+    #[test]
+    fn tab_stops() {
+        let mut state = terminal(20, 3);
+
+        run(&mut state, "a\tb\tc");
+
+        assert_eq!(state.cursor_position(), Position::new(17, 0));
+
+        run(&mut state, "\x1b[Z\x1b[Z");
+
+        assert_eq!(state.cursor_position(), Position::new(8, 0));
+
+        run(&mut state, "\x1b[2I");
+
+        assert_eq!(state.cursor_position(), Position::new(19, 0));
+
+        run(&mut state, "\r\n\x1b[3g\x1b[5G\x1bH\r\tx\tz");
+
+        assert_eq!(state.cursor_position(), Position::new(19, 1));
+
+        run(&mut state, "\x1b[1;1H\x1b[g\t");
+
+        assert_eq!(state.cursor_position(), Position::new(4, 0));
+        assert_eq!(copy_lines(&state, 2), "a\tb\tc\n\tx\tz");
+    }
+
+    #[test]
+    fn copy_tabs() {
+        let mut state = terminal(20, 1);
+
+        run(&mut state, "a\t   b");
+
+        assert_eq!(copy_lines(&state, 1), "a\t   b");
+
+        let mut state = terminal(20, 1);
+
+        run(&mut state, "\tX\r\x1b[4GY");
+
+        assert_eq!(copy_lines(&state, 1), "   Y    X");
+
+        let mut state = terminal(20, 1);
+
+        run(&mut state, "\tX\r\x1b[9GY\x1b[11GZ");
+
+        assert_eq!(copy_lines(&state, 1), "\tY Z");
+    }
+
+    #[test]
+    fn character_sets() {
+        let mut state = terminal(20, 3);
+
+        run(&mut state, "\x1b(0lqk\x1b(Bq\x1b)A\x0e#\x0f#");
+
+        assert_eq!(screen(&state)[0], "┌─┐q£#");
+
+        run(&mut state, "\r\n\x1b*0\x1bNx\x1b+<\x1bOW\x1bo(\x0f\x1b(0");
+        run(&mut state, "\x1b7\x1b(B\x1b[1mqq\x1b8q");
+
+        let cells = &state.grid().viewport().nth(1).unwrap().cells;
+
+        assert_eq!(screen(&state)[1], "│Œ¤─q");
+        assert!(!cells[3].style.bold && cells[4].style.bold);
+
+        run(&mut state, "\r\n\x1b}é");
+
+        assert_eq!(screen(&state)[2], "␋");
+    }
+
+    #[test]
+    fn clear_scrollback() {
+        let mut state = terminal(5, 2);
+
+        run(&mut state, "a\r\nb\r\nc\r\nd");
+        state.scroll_display(1);
+
+        assert_eq!(state.grid().scrollback_len(), 2);
+
+        run(&mut state, "\x1b[3J");
+
+        assert_eq!(state.grid().scrollback_len(), 0);
+        assert_eq!(state.grid().display_offset(), 0);
+        assert_eq!(screen(&state), ["c", "d"]);
+    }
+
+    #[test]
+    fn set_query_and_reset_palette_colors() {
+        let mut state = terminal(5, 2);
+
+        run(
+            &mut state,
+            "\x1b]4;1;?\x07\x1b]4;1;#123456;2;rgb:ff/8/0\x1b\\",
+        );
+        run(&mut state, "\x1b]4;1;?;2;?\x1b\\");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b]4;1;rgb:cdcd/0000/0000\x07",
+                "\x1b]4;1;rgb:1212/3434/5656\x1b\\",
+                "\x1b]4;2;rgb:ffff/8888/0000\x1b\\",
+            ]
+        );
+
+        run(
+            &mut state,
+            "\x1b]104;1\x07\x1b]4;1;?;2;?\x07\x1b]104\x07\x1b]4;2;?\x07",
+        );
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b]4;1;rgb:cdcd/0000/0000\x07",
+                "\x1b]4;2;rgb:ffff/8888/0000\x07",
+                "\x1b]4;2;rgb:0000/cdcd/0000\x07",
+            ]
+        );
+    }
+
+    #[test]
+    fn set_query_and_reset_dynamic_colors() {
+        let mut state = terminal(5, 2);
+
+        run(&mut state, "\x1b]10;?\x07\x1b]11;?\x1b\\");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b]10;rgb:ffff/ffff/ffff\x07",
+                "\x1b]11;rgb:0000/0000/0000\x1b\\",
+            ]
+        );
+
+        run(
+            &mut state,
+            "\x1b]10;#102030;rgb:4040/5050/6060\x07\x1b]10;?;?\x07",
+        );
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b]10;rgb:1010/2020/3030\x07",
+                "\x1b]11;rgb:4040/5050/6060\x07",
+            ]
+        );
+        assert!(state.take_palette_changed());
+
+        run(&mut state, "\x1b]110\x07\x1b]111\x1b\\\x1b]10;?;?\x07");
+
+        assert_eq!(
+            state.take_replies(),
+            [
+                "\x1b]10;rgb:ffff/ffff/ffff\x07",
+                "\x1b]11;rgb:0000/0000/0000\x07",
+            ]
+        );
+    }
+
+    #[test]
+    fn keyboard_flags_stack() {
+        let mut state = terminal(5, 2);
+
+        run(&mut state, "\x1b[?u\x1b[>1u\x1b[>5u\x1b[?u");
+
+        assert_eq!(state.take_replies(), ["\x1b[?0u", "\x1b[?5u"]);
+
+        run(
+            &mut state,
+            "\x1b[=8;2u\x1b[?u\x1b[=1;3u\x1b[?u\x1b[=2u\x1b[?u",
+        );
+
+        assert_eq!(state.take_replies(), ["\x1b[?13u", "\x1b[?12u", "\x1b[?2u"]);
+
+        run(&mut state, "\x1b[<u\x1b[?u\x1b[<5u\x1b[?u");
+
+        assert_eq!(state.take_replies(), ["\x1b[?1u", "\x1b[?0u"]);
+
+        run(&mut state, "\x1b[>1u\x1b[?1049h\x1b[?u\x1b[?1049l\x1b[?u");
+
+        assert_eq!(state.take_replies(), ["\x1b[?0u", "\x1b[?1u"]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::{env, fs, io};
+
+pub const NAME: &str = "milkshake";
+pub const FALLBACK_NAME: &str = "xterm-256color";
+
+static SOURCE: &str = include_str!("../terminfo/milkshake.terminfo");
+
+/// Termcap names that XTGETTCAP may ask for instead of their terminfo names.
+static ALIASES: &[(&str, &str)] = &[("Co", "colors")];
+
+/// Looks a capability up in the bundled entry, for XTGETTCAP.
+///
+/// Boolean capabilities have no value, numbers are given in decimal and strings unescaped.
+pub fn capability(name: &str) -> Option<Option<String>> {
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, name)| name);
+
+    fields().find_map(|field| {
+        if field == name {
+            return Some(None);
+        }
+
+        let value = field.strip_prefix(name)?;
+
+        match value.strip_prefix('#') {
+            Some(number) => Some(Some(number.to_string())),
+            None => value.strip_prefix('=').map(|value| Some(unescape(value))),
+        }
+    })
+}
+
+/// The capabilities in the bundled entry, which follow the line of names.
+fn fields() -> impl Iterator<Item = &'static str> {
+    SOURCE
+        .lines()
+        .filter(|line| line.starts_with(char::is_whitespace))
+        .flat_map(|line| {
+            let mut fields = Vec::new();
+            let mut start = 0;
+            let mut escaped = false;
+
+            for (index, character) in line.char_indices() {
+                match character {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    ',' => {
+                        fields.push(line[start..index].trim());
+                        start = index + 1;
+                    }
+                    _ => {}
+                }
+            }
+
+            fields
+        })
+}
+
+fn unescape(value: &str) -> String {
+    let mut string = String::new();
+    let mut chars = value.chars();
+
+    while let Some(character) = chars.next() {
+        match character {
+            '\\' => match chars.next() {
+                Some('E' | 'e') => string.push('\x1b'),
+                Some('n' | 'l') => string.push('\n'),
+                Some('r') => string.push('\r'),
+                Some('t') => string.push('\t'),
+                Some('b') => string.push('\x08'),
+                Some('f') => string.push('\x0c'),
+                Some('s') => string.push(' '),
+                Some(digit @ '0'..='7') => {
+                    let mut code = digit.to_digit(8).unwrap_or_default();
+
+                    for _ in 0..2 {
+                        let Some(digit) = chars.clone().next().and_then(|digit| digit.to_digit(8))
+                        else {
+                            break;
+                        };
+
+                        code = code * 8 + digit;
+                        chars.next();
+                    }
+
+                    string.extend(char::from_u32(code));
+                }
+                Some(character) => string.push(character),
+                None => {}
+            },
+            '^' => match chars.next() {
+                Some('?') => string.push('\x7f'),
+                Some(character) => string.push(char::from(character as u8 & 0x1f)),
+                None => string.push('^'),
+            },
+            _ => string.push(character),
+        }
+    }
+
+    string
+}
+
+/// Compiles the bundled entry into `~/.terminfo` with `tic`, unless that exact entry is already
+/// there.
+pub fn install() -> io::Result<()> {
+    let home = env::var_os("HOME")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+
+    let directory = PathBuf::from(home).join(".terminfo");
+
+    // The source is kept beside the compiled entry, to tell when a new version needs compiling.
+    let installed_source = directory.join(format!("{NAME}.terminfo"));
+
+    // ncurses files entries under their first letter, macOS under its hex code.
+    if ["m", "6d"]
+        .iter()
+        .any(|prefix| directory.join(prefix).join(NAME).exists())
+        && fs::read_to_string(&installed_source).is_ok_and(|source| source == SOURCE)
+    {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&directory)?;
+
+    let source = env::temp_dir().join(format!("{NAME}-{}.terminfo", process::id()));
+
+    fs::write(&source, SOURCE)?;
+
+    let status = Command::new("tic")
+        .arg("-x")
+        .arg("-o")
+        .arg(&directory)
+        .arg(&source)
+        .status();
+
+    fs::remove_file(&source)?;
+
+    let status = status?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("tic exited with {status}")));
+    }
+
+    fs::write(&installed_source, SOURCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_up_capabilities() {
+        assert_eq!(capability("Co").unwrap().as_deref(), Some("256"));
+        assert_eq!(capability("colors").unwrap().as_deref(), Some("256"));
+        assert_eq!(capability("RGB"), Some(None));
+        assert_eq!(capability("XT"), None);
+        assert_eq!(capability("kf1").unwrap().as_deref(), Some("\x1bOP"));
+        assert_eq!(capability("kbs").unwrap().as_deref(), Some("\x7f"));
+        assert_eq!(capability("oc").unwrap().as_deref(), Some("\x1b]104\x07"));
+        assert_eq!(
+            capability("cup").unwrap().as_deref(),
+            Some("\x1b[%i%p1%d;%p2%dH")
+        );
+    }
+}
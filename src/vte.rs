@@ -1,5 +1,8 @@
-use bevy::math::UVec2;
+use crate::charset::{Charset, CharsetIndex};
+use crate::grid::Position;
+use crate::mouse::{MouseEncoding, MouseMode};
 use compact_str::CompactString;
+use std::str;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum NamedColor {
@@ -51,11 +54,96 @@ pub enum AnsiColor {
     Rgb(u8, u8, u8),
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorSlot {
+    Index(u8),
+    Foreground,
+    Background,
+    Cursor,
+    SelectionBackground,
+    SelectionForeground,
+}
+
+impl ColorSlot {
+    pub fn dynamic(code: u16) -> Option<Self> {
+        let slot = match code {
+            10 => Self::Foreground,
+            11 => Self::Background,
+            12 => Self::Cursor,
+            17 => Self::SelectionBackground,
+            19 => Self::SelectionForeground,
+            _ => return None,
+        };
+
+        Some(slot)
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Index(_) => 4,
+            Self::Foreground => 10,
+            Self::Background => 11,
+            Self::Cursor => 12,
+            Self::SelectionBackground => 17,
+            Self::SelectionForeground => 19,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnderlineStyle {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+/// A cursor style requested with DECSCUSR.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub blink: bool,
+}
+
+/// The flavour of alternative buffer switch, from DECSET 47, 1047 or 1049.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlternativeBuffer {
+    Plain,
+    ClearOnExit,
+    SaveCursor,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum VteEvent {
     Echo(char),
     Backspace,
-    Goto(UVec2),
+    HorizontalTab(u32),
+    BackwardTab(u32),
+    SetTabStop,
+    ClearTabStop,
+    ClearAllTabStops,
+    LineFeed,
+    Index,
+    ReverseIndex,
+    NextLine,
+    SetScrollRegion(u32, Option<u32>),
+    ScreenAlignmentTest,
+    InsertLines(u32),
+    DeleteLines(u32),
+    InsertCharacters(u32),
+    DeleteCharacters(u32),
+    EraseCharacters(u32),
+    Goto(Position),
     GotoX(u32),
     GotoY(u32),
     LineUp(u32),
@@ -66,20 +154,77 @@ pub enum VteEvent {
     MoveRight(u32),
     SaveCursorPosition,
     RestoreCursorPosition,
-    EnableAlternativeBuffer,
-    DisableAlternativeBuffer,
+    DesignateCharset(CharsetIndex, Charset),
+    InvokeCharset(CharsetIndex),
+    InvokeCharsetRight(CharsetIndex),
+    SingleShift(CharsetIndex),
+    EnableApplicationCursor,
+    DisableApplicationCursor,
+    EnableApplicationKeypad,
+    DisableApplicationKeypad,
+    PushKeyboardFlags(u8),
+    PopKeyboardFlags(u32),
+    SetKeyboardFlags {
+        flags: u8,
+        mode: u8,
+    },
+    QueryKeyboardFlags,
+    ShowCursor,
+    HideCursor,
+    SetCursorStyle(Option<CursorStyle>),
+    SetMouseMode(MouseMode),
+    SetMouseEncoding(MouseEncoding),
+    EnableAlternativeBuffer(AlternativeBuffer),
+    DisableAlternativeBuffer(AlternativeBuffer),
+    EnableAutoWrap,
+    DisableAutoWrap,
     EnableBracketedPaste,
     DisableBracketedPaste,
     ReportCursorPosition,
+    ReportDeviceAttributes,
+    ReportSecondaryDeviceAttributes,
+    ReportTerminalId,
+    ReportVersion,
+    ReportMode(u16),
+    ReportPrivateMode(u16),
+    ReportSetting(CompactString),
+    ReportCapability(CompactString),
     Reset,
     Bold,
     Dim,
     Italic,
-    Underline,
+    Underline(UnderlineStyle),
+    Blink,
+    Inverse,
+    Hidden,
+    Strikethrough,
+    Overline,
+    ResetIntensity,
+    ResetItalic,
+    ResetUnderline,
+    ResetBlink,
+    ResetInverse,
+    ResetHidden,
+    ResetStrikethrough,
+    ResetOverline,
     Foreground(AnsiColor),
     ResetForeground,
     Background(AnsiColor),
     ResetBackground,
+    UnderlineColor(AnsiColor),
+    ResetUnderlineColor,
+    SetColor(ColorSlot, [u8; 3]),
+    QueryColor {
+        slot: ColorSlot,
+        bell: bool,
+    },
+    ResetColor(ColorSlot),
+    ResetPalette,
+    SetClipboard(CompactString),
+    QueryClipboard {
+        selection: CompactString,
+        bell: bool,
+    },
     SetTitle(CompactString),
     RemoveTitle,
     Image(CompactString),
@@ -98,8 +243,19 @@ pub trait VteHandler {
     }
 }
 
+/// A device control string being collected between `hook` and `unhook`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Dcs {
+    RequestSetting,
+    RequestCapability,
+}
+
+/// Longest device control string payload kept; the rest is dropped.
+const DCS_LIMIT: usize = 1024;
+
 struct Performer<T: VteHandler> {
     state: T,
+    dcs: Option<(Dcs, Vec<u8>)>,
 }
 
 pub struct Vte<T: VteHandler> {
@@ -120,62 +276,126 @@ impl<T: VteHandler> Vte<T> {
             self.parser.advance(&mut self.performer, *byte);
         }
     }
+
+    pub fn handler(&self) -> &T {
+        &self.performer.state
+    }
 }
 
 impl<T: VteHandler> Performer<T> {
     pub fn new(state: T) -> Self {
-        Self { state }
+        Self { state, dcs: None }
     }
 
     pub fn sgr(&mut self, iter: &mut vte::ParamsIter<'_>) {
-        let Some(param) = next(iter) else {
-            return;
-        };
-
-        match param {
-            0 => self.state.vte_event(VteEvent::Reset),
-
-            1 => self.state.vte_event(VteEvent::Bold),
-            2 => self.state.vte_event(VteEvent::Dim),
-            3 => self.state.vte_event(VteEvent::Italic),
-            4 => self.state.vte_event(VteEvent::Underline),
-
-            30..=37 => self
-                .state
-                .vte_event(VteEvent::Foreground(AnsiColor::Standard(StandardColor {
-                    color: NamedColor::new(param - 30).unwrap(),
-                    intensity: Intensity::Normal,
-                }))),
-
-            39 => self.state.vte_event(VteEvent::ResetForeground),
+        while let Some(param) = iter.next() {
+            let event = match param {
+                [0] => VteEvent::Reset,
+
+                [1] => VteEvent::Bold,
+                [2] => VteEvent::Dim,
+                [3] => VteEvent::Italic,
+                [4] | [4, 1] => VteEvent::Underline(UnderlineStyle::Single),
+                [4, 0] => VteEvent::ResetUnderline,
+                [4, 2] | [21] => VteEvent::Underline(UnderlineStyle::Double),
+                [4, 3] => VteEvent::Underline(UnderlineStyle::Curly),
+                [4, 4] => VteEvent::Underline(UnderlineStyle::Dotted),
+                [4, 5] => VteEvent::Underline(UnderlineStyle::Dashed),
+                [5] | [6] => VteEvent::Blink,
+                [7] => VteEvent::Inverse,
+                [8] => VteEvent::Hidden,
+                [9] => VteEvent::Strikethrough,
+
+                [22] => VteEvent::ResetIntensity,
+                [23] => VteEvent::ResetItalic,
+                [24] => VteEvent::ResetUnderline,
+                [25] => VteEvent::ResetBlink,
+                [27] => VteEvent::ResetInverse,
+                [28] => VteEvent::ResetHidden,
+                [29] => VteEvent::ResetStrikethrough,
+
+                [color @ 30..=37] => VteEvent::Foreground(standard(color - 30, Intensity::Normal)),
+                [38, ..] => match extended_color(param, iter) {
+                    Some(color) => VteEvent::Foreground(color),
+                    None => continue,
+                },
+                [39] => VteEvent::ResetForeground,
+
+                [color @ 40..=47] => VteEvent::Background(standard(color - 40, Intensity::Normal)),
+                [48, ..] => match extended_color(param, iter) {
+                    Some(color) => VteEvent::Background(color),
+                    None => continue,
+                },
+                [49] => VteEvent::ResetBackground,
+
+                [53] => VteEvent::Overline,
+                [55] => VteEvent::ResetOverline,
+
+                [58, ..] => match extended_color(param, iter) {
+                    Some(color) => VteEvent::UnderlineColor(color),
+                    None => continue,
+                },
+                [59] => VteEvent::ResetUnderlineColor,
+
+                [color @ 90..=97] => VteEvent::Foreground(standard(color - 90, Intensity::Bright)),
+                [color @ 100..=107] => {
+                    VteEvent::Background(standard(color - 100, Intensity::Bright))
+                }
 
-            40..=47 => self
-                .state
-                .vte_event(VteEvent::Background(AnsiColor::Standard(StandardColor {
-                    color: NamedColor::new(param - 40).unwrap(),
-                    intensity: Intensity::Normal,
-                }))),
+                _ => {
+                    log::error!("uncaught SGR: {param:?}");
 
-            49 => self.state.vte_event(VteEvent::ResetBackground),
+                    continue;
+                }
+            };
 
-            90..=97 => self
-                .state
-                .vte_event(VteEvent::Foreground(AnsiColor::Standard(StandardColor {
-                    color: NamedColor::new(param - 90).unwrap(),
-                    intensity: Intensity::Normal,
-                }))),
+            self.state.vte_event(event);
+        }
+    }
+}
 
-            100..=107 => self
-                .state
-                .vte_event(VteEvent::Background(AnsiColor::Standard(StandardColor {
-                    color: NamedColor::new(param - 100).unwrap(),
-                    intensity: Intensity::Normal,
-                }))),
+impl<T: VteHandler> Performer<T> {
+    fn color_spec(&mut self, slot: ColorSlot, spec: &[u8], bell: bool) {
+        if spec == b"?" {
+            self.state.vte_event(VteEvent::QueryColor { slot, bell });
+        } else if let Some(color) = parse_color_spec(spec) {
+            self.state.vte_event(VteEvent::SetColor(slot, color));
+        }
+    }
 
+    fn private_mode(&mut self, mode: u16, enable: bool) {
+        let event = match (mode, enable) {
+            (1, true) => VteEvent::EnableApplicationCursor,
+            (1, false) => VteEvent::DisableApplicationCursor,
+            (7, true) => VteEvent::EnableAutoWrap,
+            (7, false) => VteEvent::DisableAutoWrap,
+            (25, true) => VteEvent::ShowCursor,
+            (25, false) => VteEvent::HideCursor,
+            (9, true) => VteEvent::SetMouseMode(MouseMode::Press),
+            (1000, true) => VteEvent::SetMouseMode(MouseMode::Click),
+            (1002, true) => VteEvent::SetMouseMode(MouseMode::Drag),
+            (1003, true) => VteEvent::SetMouseMode(MouseMode::Motion),
+            (9 | 1000 | 1002 | 1003, false) => VteEvent::SetMouseMode(MouseMode::None),
+            (1005, true) => VteEvent::SetMouseEncoding(MouseEncoding::Utf8),
+            (1006, true) => VteEvent::SetMouseEncoding(MouseEncoding::Sgr),
+            (1015, true) => VteEvent::SetMouseEncoding(MouseEncoding::Urxvt),
+            (1005 | 1006 | 1015, false) => VteEvent::SetMouseEncoding(MouseEncoding::Default),
+            (47, true) => VteEvent::EnableAlternativeBuffer(AlternativeBuffer::Plain),
+            (47, false) => VteEvent::DisableAlternativeBuffer(AlternativeBuffer::Plain),
+            (1047, true) => VteEvent::EnableAlternativeBuffer(AlternativeBuffer::ClearOnExit),
+            (1047, false) => VteEvent::DisableAlternativeBuffer(AlternativeBuffer::ClearOnExit),
+            (1049, true) => VteEvent::EnableAlternativeBuffer(AlternativeBuffer::SaveCursor),
+            (1049, false) => VteEvent::DisableAlternativeBuffer(AlternativeBuffer::SaveCursor),
+            (2004, true) => VteEvent::EnableBracketedPaste,
+            (2004, false) => VteEvent::DisableBracketedPaste,
             _ => {
-                bevy::prelude::error!("uncaught SGR: {param}");
+                log::error!("uncaught private mode: {mode} {enable}");
+
+                return;
             }
-        }
+        };
+
+        self.state.vte_event(event);
     }
 }
 
@@ -186,19 +406,103 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
 
     fn execute(&mut self, byte: u8) {
         match byte {
-            b'\t' => self.state.vte_event(VteEvent::Echo('\t')),
+            b'\t' => self.state.vte_event(VteEvent::HorizontalTab(1)),
             b'\x08' => self.state.vte_event(VteEvent::Backspace),
             b'\r' => self.state.vte_event(VteEvent::GotoX(0)),
-            b'\n' => self.state.vte_event(VteEvent::LineDown(1)),
+            b'\n' | b'\x0b' | b'\x0c' => self.state.vte_event(VteEvent::LineFeed),
+            b'\x0e' => self
+                .state
+                .vte_event(VteEvent::InvokeCharset(CharsetIndex::G1)),
+            b'\x0f' => self
+                .state
+                .vte_event(VteEvent::InvokeCharset(CharsetIndex::G0)),
             _ => {}
         }
     }
 
-    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
         let Some(param) = params.first() else {
             return;
         };
 
+        let code = str::from_utf8(param)
+            .ok()
+            .and_then(|code| code.parse::<u16>().ok());
+
+        match code {
+            Some(4) => {
+                for pair in params[1..].chunks_exact(2) {
+                    let Some(index) = str::from_utf8(pair[0])
+                        .ok()
+                        .and_then(|index| index.parse::<u8>().ok())
+                    else {
+                        continue;
+                    };
+
+                    self.color_spec(ColorSlot::Index(index), pair[1], bell_terminated);
+                }
+
+                return;
+            }
+            Some(code @ 10..=19) => {
+                for (code, spec) in (code..=19).zip(&params[1..]) {
+                    if let Some(slot) = ColorSlot::dynamic(code) {
+                        self.color_spec(slot, spec, bell_terminated);
+                    }
+                }
+
+                return;
+            }
+            Some(104) => {
+                if params.len() == 1 {
+                    self.state.vte_event(VteEvent::ResetPalette);
+                }
+
+                for index in &params[1..] {
+                    let Some(index) = str::from_utf8(index)
+                        .ok()
+                        .and_then(|index| index.parse::<u8>().ok())
+                    else {
+                        continue;
+                    };
+
+                    self.state
+                        .vte_event(VteEvent::ResetColor(ColorSlot::Index(index)));
+                }
+
+                return;
+            }
+            Some(52) => {
+                let (Some(selection), Some(data)) = (params.get(1), params.get(2)) else {
+                    return;
+                };
+
+                if *data == b"?" {
+                    self.state.vte_event(VteEvent::QueryClipboard {
+                        selection: CompactString::from_utf8_lossy(selection),
+                        bell: bell_terminated,
+                    });
+                } else if let Ok(text) =
+                    base64::Engine::decode(&base64::engine::general_purpose::STANDARD, data)
+                {
+                    self.state
+                        .vte_event(VteEvent::SetClipboard(CompactString::from_utf8_lossy(
+                            &text,
+                        )));
+                }
+
+                return;
+            }
+            Some(code @ 110..=119) => {
+                if let Some(slot) = ColorSlot::dynamic(code - 100) {
+                    self.state.vte_event(VteEvent::ResetColor(slot));
+                }
+
+                return;
+            }
+            _ => {}
+        }
+
         match *param {
             b"0" => match params.get(1) {
                 Some(title) => self
@@ -211,7 +515,7 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
                     return;
                 };
 
-                let Some(image) = image.split(|byte| *byte == b':').last() else {
+                let Some(image) = image.split(|byte| *byte == b':').next_back() else {
                     return;
                 };
 
@@ -222,10 +526,130 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
         }
     }
 
+    fn hook(&mut self, _params: &vte::Params, intermediates: &[u8], _ignore: bool, action: char) {
+        let dcs = match (intermediates, action) {
+            (b"$", 'q') => Dcs::RequestSetting,
+            (b"+", 'q') => Dcs::RequestCapability,
+            _ => {
+                log::error!(
+                    "uncaught DCS: \\x1bP{}{action}",
+                    String::from_utf8_lossy(intermediates)
+                );
+
+                return;
+            }
+        };
+
+        self.dcs = Some((dcs, Vec::new()));
+    }
+
+    fn put(&mut self, byte: u8) {
+        if let Some((_, data)) = &mut self.dcs {
+            if data.len() < DCS_LIMIT {
+                data.push(byte);
+            }
+        }
+    }
+
+    fn unhook(&mut self) {
+        let Some((dcs, data)) = self.dcs.take() else {
+            return;
+        };
+
+        match dcs {
+            Dcs::RequestSetting => {
+                self.state
+                    .vte_event(VteEvent::ReportSetting(CompactString::from_utf8_lossy(
+                        &data,
+                    )))
+            }
+            Dcs::RequestCapability => {
+                for name in data.split(|byte| *byte == b';') {
+                    let Some(name) = decode_hex(name) else {
+                        continue;
+                    };
+
+                    self.state.vte_event(VteEvent::ReportCapability(
+                        CompactString::from_utf8_lossy(&name),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'D') => self.state.vte_event(VteEvent::Index),
+            ([], b'E') => self.state.vte_event(VteEvent::NextLine),
+            ([], b'M') => self.state.vte_event(VteEvent::ReverseIndex),
+            ([], b'H') => self.state.vte_event(VteEvent::SetTabStop),
+            ([], b'7') => self.state.vte_event(VteEvent::SaveCursorPosition),
+            ([], b'8') => self.state.vte_event(VteEvent::RestoreCursorPosition),
+            ([b'#'], b'8') => self.state.vte_event(VteEvent::ScreenAlignmentTest),
+            ([index @ (b'(' | b')' | b'*' | b'+'), rest @ ..], byte) => {
+                let index = match index {
+                    b'(' => CharsetIndex::G0,
+                    b')' => CharsetIndex::G1,
+                    b'*' => CharsetIndex::G2,
+                    _ => CharsetIndex::G3,
+                };
+
+                let charset = match (rest, byte) {
+                    ([], b'B') => Charset::Ascii,
+                    ([], b'A') => Charset::Uk,
+                    ([], b'0') => Charset::DecSpecialGraphics,
+                    ([], b'<') | ([b'%'], b'5') => Charset::DecSupplemental,
+                    _ => {
+                        log::error!(
+                            "uncaught charset: \\x1b{}{}",
+                            String::from_utf8_lossy(intermediates),
+                            byte as char
+                        );
+
+                        return;
+                    }
+                };
+
+                self.state
+                    .vte_event(VteEvent::DesignateCharset(index, charset));
+            }
+            ([], b'n') => self
+                .state
+                .vte_event(VteEvent::InvokeCharset(CharsetIndex::G2)),
+            ([], b'o') => self
+                .state
+                .vte_event(VteEvent::InvokeCharset(CharsetIndex::G3)),
+            ([], b'~') => self
+                .state
+                .vte_event(VteEvent::InvokeCharsetRight(CharsetIndex::G1)),
+            ([], b'}') => self
+                .state
+                .vte_event(VteEvent::InvokeCharsetRight(CharsetIndex::G2)),
+            ([], b'|') => self
+                .state
+                .vte_event(VteEvent::InvokeCharsetRight(CharsetIndex::G3)),
+            ([], b'N') => self
+                .state
+                .vte_event(VteEvent::SingleShift(CharsetIndex::G2)),
+            ([], b'O') => self
+                .state
+                .vte_event(VteEvent::SingleShift(CharsetIndex::G3)),
+            ([], b'=') => self.state.vte_event(VteEvent::EnableApplicationKeypad),
+            ([], b'>') => self.state.vte_event(VteEvent::DisableApplicationKeypad),
+            _ => {
+                log::error!(
+                    "uncaught ESC: \\x1b{}{}",
+                    String::from_utf8_lossy(intermediates),
+                    byte as char
+                );
+            }
+        }
+    }
+
     fn csi_dispatch(
         &mut self,
         params: &vte::Params,
-        _intermediates: &[u8],
+        intermediates: &[u8],
         _ignore: bool,
         action: char,
     ) {
@@ -241,6 +665,16 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
             'F' => self.state.vte_event(VteEvent::LineUp(next_axis(iter))),
 
             'G' => self.state.vte_event(VteEvent::GotoX(next_axis(iter) - 1)),
+            'I' => self
+                .state
+                .vte_event(VteEvent::HorizontalTab(next_axis(iter))),
+            'Z' => self.state.vte_event(VteEvent::BackwardTab(next_axis(iter))),
+            'g' => match next(iter) {
+                Some(0) | None => self.state.vte_event(VteEvent::ClearTabStop),
+                Some(3) => self.state.vte_event(VteEvent::ClearAllTabStops),
+                _ => {}
+            },
+            'd' => self.state.vte_event(VteEvent::GotoY(next_axis(iter) - 1)),
             'H' | 'f' => self.state.vte_event(VteEvent::Goto(next_position(iter))),
 
             'm' => self.sgr(iter),
@@ -249,6 +683,25 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
                     self.state.vte_event(VteEvent::ReportCursorPosition)
                 }
             }
+            'c' if next(iter).unwrap_or(0) == 0 => match intermediates {
+                [] => self.state.vte_event(VteEvent::ReportDeviceAttributes),
+                [b'>'] => self
+                    .state
+                    .vte_event(VteEvent::ReportSecondaryDeviceAttributes),
+                [b'='] => self.state.vte_event(VteEvent::ReportTerminalId),
+                _ => {}
+            },
+            'q' if intermediates == b">" => self.state.vte_event(VteEvent::ReportVersion),
+            'p' if intermediates == b"$" => {
+                if let Some(mode) = next(iter) {
+                    self.state.vte_event(VteEvent::ReportMode(mode));
+                }
+            }
+            'p' if intermediates == b"?$" => {
+                if let Some(mode) = next(iter) {
+                    self.state.vte_event(VteEvent::ReportPrivateMode(mode));
+                }
+            }
 
             'J' => match next(iter) {
                 Some(0) | None => self.state.vte_event(VteEvent::ClearDown),
@@ -265,10 +718,69 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
                 _ => {}
             },
 
+            '@' if intermediates.is_empty() => self
+                .state
+                .vte_event(VteEvent::InsertCharacters(next_axis(iter))),
+            'L' => self.state.vte_event(VteEvent::InsertLines(next_axis(iter))),
+            'M' => self.state.vte_event(VteEvent::DeleteLines(next_axis(iter))),
+            'P' => self
+                .state
+                .vte_event(VteEvent::DeleteCharacters(next_axis(iter))),
+            'X' => self
+                .state
+                .vte_event(VteEvent::EraseCharacters(next_axis(iter))),
+
+            'r' if intermediates.is_empty() => {
+                let top = next_axis(iter) - 1;
+                let bottom = next(iter).filter(|bottom| *bottom > 0).map(u32::from);
+
+                self.state.vte_event(VteEvent::SetScrollRegion(top, bottom));
+            }
+
+            'h' | 'l' if intermediates == b"?" => {
+                for param in iter {
+                    self.private_mode(param[0], action == 'h');
+                }
+            }
+
+            'q' if intermediates == b" " => {
+                let (shape, blink) = match next(iter).unwrap_or(0) {
+                    0 => {
+                        self.state.vte_event(VteEvent::SetCursorStyle(None));
+
+                        return;
+                    }
+                    1 => (CursorShape::Block, true),
+                    2 => (CursorShape::Block, false),
+                    3 => (CursorShape::Underline, true),
+                    4 => (CursorShape::Underline, false),
+                    5 => (CursorShape::Bar, true),
+                    6 => (CursorShape::Bar, false),
+                    _ => return,
+                };
+
+                self.state
+                    .vte_event(VteEvent::SetCursorStyle(Some(CursorStyle { shape, blink })));
+            }
+
             's' => self.state.vte_event(VteEvent::SaveCursorPosition),
-            'u' => self.state.vte_event(VteEvent::RestoreCursorPosition),
+            'u' => match intermediates {
+                [] => self.state.vte_event(VteEvent::RestoreCursorPosition),
+                [b'>'] => self
+                    .state
+                    .vte_event(VteEvent::PushKeyboardFlags(next(iter).unwrap_or(0) as u8)),
+                [b'<'] => self
+                    .state
+                    .vte_event(VteEvent::PopKeyboardFlags(next_axis(iter))),
+                [b'='] => self.state.vte_event(VteEvent::SetKeyboardFlags {
+                    flags: next(iter).unwrap_or(0) as u8,
+                    mode: next(iter).unwrap_or(1) as u8,
+                }),
+                [b'?'] => self.state.vte_event(VteEvent::QueryKeyboardFlags),
+                _ => {}
+            },
             _ => {
-                bevy::prelude::error!(
+                log::error!(
                     "uncaught CSI: \\x1b[{}{action}",
                     iter.map(|params| params
                         .iter()
@@ -283,6 +795,73 @@ impl<T: VteHandler> vte::Perform for Performer<T> {
     }
 }
 
+fn parse_color_spec(spec: &[u8]) -> Option<[u8; 3]> {
+    let spec = str::from_utf8(spec).ok()?;
+
+    let scale = |channel: &str| {
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1u32 << (channel.len() * 4)) - 1;
+
+        (1..=4)
+            .contains(&channel.len())
+            .then(|| ((value * 255) / max) as u8)
+    };
+
+    if let Some(spec) = spec.strip_prefix("rgb:") {
+        let mut channels = spec.split('/').map(scale);
+        let color = [channels.next()??, channels.next()??, channels.next()??];
+
+        return channels.next().is_none().then_some(color);
+    }
+
+    let spec = spec.strip_prefix('#')?;
+    let width = spec.len() / 3;
+
+    if spec.len() % 3 != 0 || !(1..=4).contains(&width) {
+        return None;
+    }
+
+    let channel = |index: usize| {
+        let channel = spec.get(index * width..(index + 1) * width)?;
+
+        Some((u32::from_str_radix(channel, 16).ok()? << (16 - width * 4) >> 8) as u8)
+    };
+
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.chunks_exact(2)
+        .map(|pair| u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn standard(color: u16, intensity: Intensity) -> AnsiColor {
+    AnsiColor::Standard(StandardColor {
+        color: NamedColor::new(color).unwrap(),
+        intensity,
+    })
+}
+
+fn extended_color(param: &[u16], iter: &mut vte::ParamsIter<'_>) -> Option<AnsiColor> {
+    let rgb = |r: u16, g: u16, b: u16| AnsiColor::Rgb(r as u8, g as u8, b as u8);
+
+    match *param {
+        [_, 5, index] => Some(AnsiColor::Index(index as u8)),
+        [_, 2, r, g, b] | [_, 2, _, r, g, b] => Some(rgb(r, g, b)),
+        [_] => match next(iter)? {
+            5 => Some(AnsiColor::Index(next(iter)? as u8)),
+            2 => Some(rgb(next(iter)?, next(iter)?, next(iter)?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn next(iter: &mut vte::ParamsIter<'_>) -> Option<u16> {
     iter.next().and_then(|params| params.first().copied())
 }
@@ -291,9 +870,10 @@ fn next_axis(iter: &mut vte::ParamsIter<'_>) -> u32 {
     next(iter).unwrap_or(1).max(1).into()
 }
 
-fn next_position(iter: &mut vte::ParamsIter<'_>) -> UVec2 {
-    let y = next_axis(iter);
-    let x = next_axis(iter);
+/// Reads a one-based row and column into a zero-based position.
+fn next_position(iter: &mut vte::ParamsIter<'_>) -> Position {
+    let y = next_axis(iter) - 1;
+    let x = next_axis(iter) - 1;
 
-    UVec2::new(x, y)
+    Position::new(x, y)
 }